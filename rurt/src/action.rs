@@ -1,15 +1,35 @@
 use std::borrow::Cow;
-use std::fs;
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io;
 use std::path::{Path, PathBuf};
-use std::process::ExitCode;
+use std::process::{ExitCode, ExitStatus, Stdio};
+use std::thread;
 
-use crate::draw::RightPane;
-use crate::ui_state::{matching_preview, Ui};
-use crate::walk::{Mode, MODES};
+use crate::alt_screen::suspended;
+use crate::archive::{self, extract, is_archive};
+use crate::cleanup::{item_bytes, Cleanup, Disposal};
+use crate::draw::{PreviewMode, RightPane};
+use crate::du::DiskUsage;
+use crate::ignores::{append_to_gitignore, Ignores};
+use crate::photo::CaptureDates;
+use crate::previewers::{shell_command, shell_command_with};
+use crate::ui_state::{
+    matching_preview, page_preview, preview_key_path, preview_selection_text, preview_source_line,
+    preview_window, toggle_preview_fold, turn_preview_page, SortOrder, Ui,
+};
+use crate::walk::{Mode, Recursion, MODES};
 use crate::App;
 use anyhow::{anyhow, bail};
+use arboard::Clipboard;
 use convert_case::{Case, Casing};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::info;
+
+/// Lines to go back by, when scrolling up past the start of a page of a big file
+const PAGE_BACK: usize = 200;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Action {
@@ -22,19 +42,42 @@ pub enum Action {
     // positive is *flips coin* towards the bottom of the screen
     MoveCursor(isize),
     MovePreview(isize),
+    /// some percent of the way through the file, 100 being the end
+    PagePreview(u8),
     CyclePalette,
     CycleHidden,
     CycleIgnored,
+    AddToGitignore,
+    AddExtensionToGitignore,
     CycleModeSkipping(Vec<Mode>),
     SetMode(Mode),
     CycleRecursion,
+    CycleSource,
+    ToggleWatch,
+    ToggleDiskUsage,
+    CycleSort,
+    ToggleCleanup,
+    ToggleSelect,
+    RemoveSelected,
+    Dispose(Disposal),
+    Extract,
+    ToggleGrep,
+    ToggleGlobal,
     TogglePreview,
     TogglePreviewMode,
     TogglePreviewColour,
+    TogglePreviewRendered,
     SetTarget,
     Expand,
     Open,
+    Play,
     FocusGit,
+    FocusPreview,
+    CopyPreviewLines,
+    CopyKeyPath,
+    ToggleFold,
+    EditAtLine,
+    AcceptPreviewLine,
     DirBack,
     DirForward,
     Abort,
@@ -45,6 +88,8 @@ impl Action {
         match self {
             Action::MoveCursor(delta) => format!("move cursor {}", show_delta(*delta)).into(),
             Action::MovePreview(delta) => format!("move preview {}", show_delta(*delta)).into(),
+            Action::PagePreview(100) => "page preview to end".into(),
+            Action::PagePreview(percent) => format!("page preview to {percent}%").into(),
             other => format!("{:?}", other).to_case(Case::Lower).into(),
         }
     }
@@ -73,6 +118,8 @@ pub enum ActionResult {
     JustRescan,
     /// changed some config state, don't rescan
     Configured,
+    /// gave the terminal away for a while, draw everything again
+    Repaint,
     /// we're done, print something and bail
     Exit(Option<String>, ExitCode),
 }
@@ -105,7 +152,8 @@ pub fn handle_action(action: Action, app: &mut App, ui: &mut Ui) -> anyhow::Resu
             ActionResult::Configured
         }
         Action::MovePreview(delta) => {
-            let max_cursor = matching_preview(ui, view_opts.preview_mode())
+            let mode = view_opts.preview_mode();
+            let max_cursor = matching_preview(ui, mode)
                 .and_then(|p| {
                     p.data
                         .lock()
@@ -113,15 +161,32 @@ pub fn handle_action(action: Action, app: &mut App, ui: &mut Ui) -> anyhow::Resu
                         .and_then(|d| d.render.as_ref().map(|r| r.lines.len()))
                 })
                 .unwrap_or(usize::MAX);
+            let moved = (ui.preview_cursor as isize).saturating_add(delta);
+
+            // off the ends of a page of a big file, and on to the next
+            if let Some(window) = preview_window(ui, mode) {
+                match (window.next, window.back(PAGE_BACK)) {
+                    (Some(next), _) if delta > 0 && moved >= max_cursor as isize => {
+                        turn_preview_page(ui, next, 0);
+                        return Ok(ActionResult::Ignored);
+                    }
+                    (_, Some((page, from))) if moved < 0 => {
+                        turn_preview_page(ui, page, from.saturating_add_signed(moved));
+                        return Ok(ActionResult::Ignored);
+                    }
+                    _ => (),
+                }
+            }
 
-            ui.preview_cursor =
-                usize::try_from((ui.preview_cursor as isize).saturating_add(delta as isize))
-                    .unwrap_or(0)
-                    .min(max_cursor);
+            ui.preview_cursor = usize::try_from(moved).unwrap_or(0).min(max_cursor);
 
             // 'Configured' resets the preview_cursor
             ActionResult::Ignored
         }
+        Action::PagePreview(percent) => {
+            page_preview(ui, view_opts.preview_mode(), percent);
+            ActionResult::Ignored
+        }
         Action::Home => {
             dir_stack.push(here.clone());
             *here =
@@ -134,12 +199,30 @@ pub fn handle_action(action: Action, app: &mut App, ui: &mut Ui) -> anyhow::Resu
         }
         Action::CycleHidden => {
             read_opts.show_hidden = !read_opts.show_hidden;
+            ui.preview_style.hidden = read_opts.show_hidden;
             ActionResult::Navigated
         }
         Action::CycleIgnored => {
             read_opts.show_ignored = !read_opts.show_ignored;
+            ui.preview_style.ignored = read_opts.show_ignored;
             ActionResult::Navigated
         }
+        Action::AddToGitignore | Action::AddExtensionToGitignore => {
+            let Some(path) = ui.cursor_showing_path().map(|p| here.join(p)) else {
+                return Ok(ActionResult::Ignored);
+            };
+            let (file, pattern) = append_to_gitignore(
+                &path,
+                path.is_dir(),
+                action == Action::AddExtensionToGitignore,
+            )?;
+            info!("added {pattern:?} to {file:?}");
+            ui.ignores = Ignores::default();
+            ui.previews
+                .inner
+                .retain(|p| p.mode != PreviewMode::WhyIgnored);
+            ActionResult::JustRescan
+        }
         Action::CycleModeSkipping(skipped_modes) => {
             let mut new_index = (read_opts.mode_index + 1) % MODES.len();
             let mut skipped_indices = vec![];
@@ -169,10 +252,198 @@ pub fn handle_action(action: Action, app: &mut App, ui: &mut Ui) -> anyhow::Resu
             ui.bad_git_log.focus = true;
             ActionResult::Ignored
         }
+        Action::CopyPreviewLines => {
+            let Some(text) = preview_selection_text(ui, view_opts.preview_mode()) else {
+                return Ok(ActionResult::Ignored);
+            };
+            Clipboard::new()?.set_text(&text)?;
+            info!("copied {} lines to clipboard", text.lines().count());
+            ActionResult::Ignored
+        }
+        Action::CopyKeyPath => {
+            let Some(path) = preview_key_path(ui, view_opts.preview_mode()) else {
+                return Ok(ActionResult::Ignored);
+            };
+            Clipboard::new()?.set_text(&path)?;
+            info!("copied {path} to clipboard");
+            ActionResult::Ignored
+        }
+        Action::ToggleFold => {
+            toggle_preview_fold(ui, view_opts.preview_mode());
+            ActionResult::Ignored
+        }
+        Action::EditAtLine => {
+            let Some(path) = ui.cursor_showing_path().map(|p| here.join(p)) else {
+                return Ok(ActionResult::Ignored);
+            };
+            let line = preview_source_line(ui, view_opts.preview_mode()).unwrap_or(1);
+            let status = suspended(|| edit(&path, line))??;
+            if !status.success() {
+                info!("editor: {status}");
+            }
+            ActionResult::Repaint
+        }
+        Action::AcceptPreviewLine => {
+            let Some(path) = ui.cursor_showing_path().map(|p| here.join(p)) else {
+                return Ok(ActionResult::Ignored);
+            };
+            let mut result = output_path(path, app.result_opts.force_absolute_path);
+            if let Some(line) = preview_source_line(ui, view_opts.preview_mode()) {
+                result = format!("{result}:{line}");
+            }
+            ActionResult::Exit(Some(result), ExitCode::SUCCESS)
+        }
+        Action::FocusPreview => {
+            ui.preview_focus.focus = !ui.preview_focus.focus;
+            if ui.preview_focus.focus {
+                let i = view_opts
+                    .right_pane_mode
+                    .iter()
+                    .position(|p| *p == RightPane::Preview)
+                    .expect("preview mode present");
+                view_opts.right_pane_mode.rotate_left(i);
+            }
+            ActionResult::Ignored
+        }
         Action::CycleRecursion => {
             read_opts.recursion = read_opts.recursion.next();
             ActionResult::Navigated
         }
+        Action::CycleSource => {
+            read_opts.source = read_opts.source.next();
+            ActionResult::Navigated
+        }
+        Action::ToggleWatch => {
+            read_opts.watch = !read_opts.watch;
+            ActionResult::JustRescan
+        }
+        Action::ToggleDiskUsage => {
+            ui.disk_usage = match ui.disk_usage {
+                Some(_) => None,
+                None => Some(DiskUsage::new(here)),
+            };
+            ui.sorted_items.clear();
+            ActionResult::Configured
+        }
+        Action::CycleSort => {
+            ui.sort = ui.sort.next();
+            if ui.sort == SortOrder::Taken {
+                // photos may have been edited since
+                ui.capture_dates = CaptureDates::default();
+            }
+            ui.sorted_items.clear();
+            ActionResult::Configured
+        }
+        Action::ToggleCleanup => {
+            if ui.cleanup.take().is_some() {
+                ui.sort = SortOrder::Name;
+                ui.disk_usage = None;
+                ui.sorted_items.clear();
+                return Ok(ActionResult::Configured);
+            }
+            ui.cleanup = Some(Cleanup::new(here));
+            ui.sort = SortOrder::Stale;
+            ui.disk_usage.get_or_insert_with(|| DiskUsage::new(here));
+            read_opts.recursion = Recursion::All;
+            read_opts.mode_index = Mode::Files as usize;
+            ActionResult::Navigated
+        }
+        Action::ToggleSelect => {
+            let Some(item) = &ui.cursor_showing else {
+                return Ok(ActionResult::Ignored);
+            };
+            let Some(path) = item.path().map(|p| here.join(p)) else {
+                return Ok(ActionResult::Ignored);
+            };
+            match &mut ui.cleanup {
+                Some(cleanup) => cleanup.toggle(path, item_bytes(item, ui.disk_usage.as_ref())),
+                None => {
+                    if !ui.marked.remove(&path) {
+                        ui.marked.insert(path);
+                    }
+                }
+            }
+            ui.cursor.pending_move = Some(1);
+            ActionResult::Configured
+        }
+        Action::RemoveSelected => {
+            if let Some(cleanup) = ui.cleanup.as_mut().filter(|c| !c.selected.is_empty()) {
+                cleanup.confirming = true;
+            }
+            ActionResult::Ignored
+        }
+        Action::Dispose(disposal) => {
+            let Some(cleanup) = &mut ui.cleanup else {
+                return Ok(ActionResult::Ignored);
+            };
+            let count = cleanup.dispose(disposal, here);
+            info!("cleanup: {disposal:?}: {count} paths");
+            if ui.disk_usage.is_some() {
+                ui.disk_usage = Some(DiskUsage::new(here));
+            }
+            ActionResult::JustRescan
+        }
+        Action::Extract => {
+            let member = |path: &Path| {
+                archive::split(path).is_some_and(|(_, inner)| !inner.as_os_str().is_empty())
+            };
+            // what's marked, else what's under the cursor
+            let mut paths: Vec<PathBuf> = ui.marked.iter().filter(|p| member(p)).cloned().collect();
+            paths.sort();
+            if paths.is_empty() {
+                paths.extend(
+                    ui.cursor_showing_path()
+                        .map(|p| here.join(p))
+                        .filter(|p| member(p)),
+                );
+            }
+            if paths.is_empty() {
+                return Ok(ActionResult::Ignored);
+            }
+
+            // each archive is read once, however many members come out of it
+            let mut by_archive = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+            for path in &paths {
+                let (archive, inner) = archive::split(path).expect("filtered");
+                ui.marked.remove(path);
+                by_archive
+                    .entry(archive.to_path_buf())
+                    .or_default()
+                    .push(inner.to_path_buf());
+            }
+            info!("extract: {} members, in the background", paths.len());
+            thread::spawn(move || {
+                let mut failed = 0;
+                for (archive, members) in by_archive {
+                    for (inner, result) in members.iter().zip(extract(&archive, &members)) {
+                        match result {
+                            Ok(dest) => info!("extracted {inner:?} to {dest:?}"),
+                            Err(e) => {
+                                info!("extract: {inner:?}: {e}");
+                                failed += 1;
+                            }
+                        }
+                    }
+                }
+                if failed > 0 && paths.len() > 1 {
+                    info!("extract: {failed} of {} members failed", paths.len());
+                }
+            });
+            ActionResult::Ignored
+        }
+        Action::ToggleGrep => {
+            ui.grep = !ui.grep;
+            read_opts.grep = ui.grep_query();
+            ActionResult::JustRescan
+        }
+        Action::ToggleGlobal => {
+            if read_opts.paths.is_none() {
+                info!("global: no path index, see --path-index");
+                return Ok(ActionResult::Ignored);
+            }
+            read_opts.global = !read_opts.global;
+            ActionResult::JustRescan
+        }
         Action::TogglePreview => {
             view_opts.right_pane_mode.rotate_left(1);
             ActionResult::Configured
@@ -182,7 +453,11 @@ pub fn handle_action(action: Action, app: &mut App, ui: &mut Ui) -> anyhow::Resu
             ActionResult::Configured
         }
         Action::TogglePreviewColour => {
-            ui.preview_colours = !ui.preview_colours;
+            ui.preview_style.coloured = !ui.preview_style.coloured;
+            ActionResult::Configured
+        }
+        Action::TogglePreviewRendered => {
+            ui.preview_style.rendered = !ui.preview_style.rendered;
             ActionResult::Configured
         }
         Action::SetTarget => {
@@ -195,6 +470,17 @@ pub fn handle_action(action: Action, app: &mut App, ui: &mut Ui) -> anyhow::Resu
             }
             ActionResult::Ignored
         }
+        Action::Play => {
+            if let Some(showing) = ui.cursor_showing_path() {
+                let path = here.join(showing);
+                match &app.player {
+                    Some(player) => play(player, &path)?,
+                    None => open::that_detached(&path)?,
+                }
+                info!("playing {path:?}");
+            }
+            ActionResult::Ignored
+        }
         Action::Expand => {
             if let Some(name) = ui.cursor_showing_path() {
                 if read_opts.expansions.insert(here.join(name)) {
@@ -225,21 +511,27 @@ pub fn handle_action(action: Action, app: &mut App, ui: &mut Ui) -> anyhow::Resu
         Action::Abort => ActionResult::Exit(None, ExitCode::FAILURE),
         Action::Activate => {
             if let Some(name) = ui.cursor_showing_path() {
-                if let Ok(cand) = ensure_directory(here.join(name)) {
+                let path = here.join(name);
+                if is_archive_dir(ui, &path) {
+                    ui.input.reset();
+                    dir_stack.push(here.to_path_buf());
+                    *here = path;
+                    ActionResult::Navigated
+                } else if let Ok(cand) = ensure_directory(here.join(name)) {
                     ui.input.reset();
                     dir_stack.push(here.to_path_buf());
                     *here = cand;
                     ActionResult::Navigated
                 } else {
-                    let mut cand = here.join(name);
-                    if !app.result_opts.force_absolute_path {
-                        if let Ok(cwd) = std::env::current_dir() {
-                            if let Ok(stripped) = cand.strip_prefix(&cwd) {
-                                cand = stripped.to_path_buf();
-                            }
-                        }
+                    let mut result =
+                        output_path(here.join(name), app.result_opts.force_absolute_path);
+                    // editors take `path:line:col`
+                    if let Some((line, column)) =
+                        ui.cursor_showing.as_ref().and_then(|i| i.position())
+                    {
+                        result = format!("{result}:{line}:{column}");
                     }
-                    ActionResult::Exit(Some(cand.display().to_string()), ExitCode::SUCCESS)
+                    ActionResult::Exit(Some(result), ExitCode::SUCCESS)
                 }
             } else {
                 ActionResult::Exit(None, ExitCode::FAILURE)
@@ -260,9 +552,55 @@ pub fn handle_action(action: Action, app: &mut App, ui: &mut Ui) -> anyhow::Resu
     })
 }
 
-fn get_cursor_directory(current_dir: &PathBuf, ui: &Ui) -> Option<PathBuf> {
+/// Relative to where we were started, unless asked not to.
+fn output_path(path: PathBuf, force_absolute: bool) -> String {
+    if !force_absolute {
+        if let Ok(cwd) = std::env::current_dir() {
+            if let Ok(stripped) = path.strip_prefix(&cwd) {
+                return stripped.display().to_string();
+            }
+        }
+    }
+    path.display().to_string()
+}
+
+/// Run `$VISUAL` or `$EDITOR` on the terminal, which nearly all take `+line`.
+fn edit(path: &Path, line: usize) -> io::Result<ExitStatus> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let line = format!("+{line}");
+    // the editor may come with its own arguments
+    let mut command = shell_command_with(&editor, [OsStr::new(&line), path.as_os_str()]);
+    // our stdout is often being captured by a shell widget
+    if let Ok(tty) = File::options().read(true).write(true).open("/dev/tty") {
+        command.stdin(tty.try_clone()?).stdout(tty);
+    }
+    command.status()
+}
+
+/// Start the player on the file and leave it to it, without the terminal, so it can't draw
+/// over us, and in its own process group, so it plays on after we're gone.
+fn play(player: &str, path: &Path) -> io::Result<()> {
+    let mut command = shell_command(player, path);
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn()?;
+    // or it lingers as a zombie until we exit
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+fn get_cursor_directory(current_dir: &Path, ui: &Ui) -> Option<PathBuf> {
     ui.cursor_showing_path().and_then(|name| {
-        let path = current_dir.clone().join(name);
+        let path = current_dir.join(name);
+        if is_archive(&path) || is_archive_dir(ui, &path) {
+            return Some(path);
+        }
         ensure_directory(path.clone()).ok().or_else(|| {
             let path = path.as_path().parent()?;
             Some(path.into())
@@ -270,6 +608,16 @@ fn get_cursor_directory(current_dir: &PathBuf, ui: &Ui) -> Option<PathBuf> {
     })
 }
 
+/// there's nothing on disk to check inside an archive, so trust the listing
+fn is_archive_dir(ui: &Ui, path: &Path) -> bool {
+    let is_dir = ui
+        .cursor_showing
+        .as_ref()
+        .and_then(|item| item.info())
+        .is_some_and(|info| info.kind.is_dir());
+    is_dir && archive::split(path).is_some()
+}
+
 fn ensure_directory(p: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
     let canon = fs::canonicalize(p)?;
     if !fs::metadata(&canon)?.is_dir() {
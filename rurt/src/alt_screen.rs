@@ -24,6 +24,16 @@ pub fn enter_alt_screen() -> Result<DropRestore> {
     Ok(DropRestore { restore })
 }
 
+/// Hand the terminal to something else, like an editor, while `f` runs.
+pub fn suspended<T>(f: impl FnOnce() -> T) -> Result<T> {
+    disable_raw_mode()?;
+    execute!(stderr(), LeaveAlternateScreen)?;
+    let ret = f();
+    execute!(stderr(), EnterAlternateScreen)?;
+    enable_raw_mode()?;
+    Ok(ret)
+}
+
 pub struct Restore {
     restore_once: Once,
 }
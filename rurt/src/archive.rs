@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use crate::fuzz::AddItem;
+use crate::item::FileKind;
+use crate::tracked::Emitter;
+use crate::walk::ReadOpts;
+use anyhow::{anyhow, bail, Result};
+use flate2::read::GzDecoder;
+use log::info;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        [
+            (".zip", Format::Zip),
+            (".tar", Format::Tar),
+            (".tar.gz", Format::TarGz),
+            (".tgz", Format::TarGz),
+            (".tar.zst", Format::TarZst),
+            (".tzst", Format::TarZst),
+        ]
+        .into_iter()
+        .find(|(ext, _)| name.ends_with(ext))
+        .map(|(_, format)| format)
+    }
+}
+
+pub struct Entry {
+    /// relative to the root of the archive
+    pub path: PathBuf,
+    pub kind: FileKind,
+    pub size: u64,
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    Format::of(path).is_some() && path.is_file()
+}
+
+/// Split a path which runs through an archive into the archive on disk,
+/// and the path inside it (empty for the archive itself).
+pub fn split(path: &Path) -> Option<(&Path, &Path)> {
+    let archive = path.ancestors().find(|p| is_archive(p))?;
+    Some((archive, path.strip_prefix(archive).ok()?))
+}
+
+/// List the part of an archive under `prefix`, like walking a directory would.
+pub fn stream_archive(
+    tx: AddItem,
+    archive: &Path,
+    prefix: &Path,
+    read_opts: &ReadOpts,
+) -> Result<()> {
+    let mut emitter = Emitter::new(&tx, archive, prefix, read_opts);
+    visit(
+        archive,
+        |entry, _| Ok(emitter.emit(&entry.path, entry.kind)),
+    )
+}
+
+pub enum Member {
+    /// the start of a file's content
+    File(Vec<u8>),
+    /// a directory's immediate children, and their sizes
+    Dir(BTreeMap<PathBuf, (FileKind, u64)>),
+}
+
+/// Read a file out of an archive, up to `limit` bytes, or list a directory in it.
+pub fn read(archive: &Path, inner: &Path, limit: u64) -> Result<Member> {
+    let mut content = None;
+    let mut children = BTreeMap::new();
+    visit(archive, |entry, reader| {
+        if entry.path == inner && entry.kind != FileKind::Dir {
+            let mut buf = Vec::new();
+            reader.take(limit).read_to_end(&mut buf)?;
+            content = Some(buf);
+            return Ok(true);
+        }
+        let Ok(rest) = entry.path.strip_prefix(inner) else {
+            return Ok(false);
+        };
+        let mut components = rest.components();
+        let Some(child) = components.next() else {
+            return Ok(false);
+        };
+        let child = children
+            .entry(PathBuf::from(child.as_os_str()))
+            .or_insert((FileKind::Dir, 0));
+        if components.next().is_none() {
+            child.0 = entry.kind;
+        }
+        child.1 += entry.size;
+        Ok(false)
+    })?;
+
+    match content {
+        Some(content) => Ok(Member::File(content)),
+        None if children.is_empty() && !inner.as_os_str().is_empty() => {
+            bail!("{inner:?} isn't in {archive:?}")
+        }
+        None => Ok(Member::Dir(children)),
+    }
+}
+
+/// Copy each of `members`, and anything under them, out of the archive to sit next to
+/// it, in one pass over the archive.
+/// @return where each went, or why it didn't
+pub fn extract(archive: &Path, members: &[PathBuf]) -> Vec<Result<PathBuf>> {
+    let mut dests = members
+        .iter()
+        .map(|inner| {
+            let name = inner
+                .file_name()
+                .ok_or_else(|| anyhow!("nothing to extract"))?;
+            let dest = archive
+                .parent()
+                .ok_or_else(|| anyhow!("archive has no parent"))?
+                .join(name);
+            if dest.symlink_metadata().is_ok() {
+                bail!("{dest:?} already exists");
+            }
+            Ok(dest)
+        })
+        .collect::<Vec<_>>();
+
+    let mut found = vec![false; members.len()];
+    let visited = visit(archive, |entry, reader| {
+        // a member inside another one which is also being extracted gets a copy
+        let mut written: Option<PathBuf> = None;
+        for (i, inner) in members.iter().enumerate() {
+            let Ok(dest) = &dests[i] else {
+                continue;
+            };
+            let Ok(rest) = entry.path.strip_prefix(inner) else {
+                continue;
+            };
+            found[i] = true;
+            let out = if rest.as_os_str().is_empty() {
+                dest.clone()
+            } else {
+                dest.join(rest)
+            };
+            if let Err(e) = extract_entry(&entry, reader, &out, written.as_deref()) {
+                dests[i] = Err(e);
+            } else if entry.kind == FileKind::File {
+                written.get_or_insert(out);
+            }
+        }
+        Ok(false)
+    });
+
+    dests
+        .into_iter()
+        .zip(found)
+        .zip(members)
+        .map(|((dest, found), inner)| match (&visited, dest) {
+            (_, Err(e)) => Err(e),
+            (Err(e), Ok(_)) => Err(anyhow!("reading {archive:?}: {e}")),
+            (Ok(()), Ok(_)) if !found => Err(anyhow!("{inner:?} isn't in {archive:?}")),
+            (Ok(()), Ok(dest)) => Ok(dest),
+        })
+        .collect()
+}
+
+/// Write one entry out to `out`; from `copy`, if its content's already been written there.
+fn extract_entry(
+    entry: &Entry,
+    reader: &mut dyn Read,
+    out: &Path,
+    copy: Option<&Path>,
+) -> Result<()> {
+    match entry.kind {
+        FileKind::Dir => fs::create_dir_all(out)?,
+        FileKind::File => {
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = File::create_new(out)?;
+            match copy {
+                Some(copy) => io::copy(&mut File::open(copy)?, &mut file)?,
+                None => io::copy(reader, &mut file)?,
+            };
+        }
+        FileKind::Symlink | FileKind::Other => {
+            info!("extract: skipping {:?}, not a file", entry.path)
+        }
+    }
+    Ok(())
+}
+
+/// Call `f` with each entry and its content, in archive order, until it returns true.
+fn visit(archive: &Path, mut f: impl FnMut(Entry, &mut dyn Read) -> Result<bool>) -> Result<()> {
+    let format = Format::of(archive).ok_or_else(|| anyhow!("not an archive: {archive:?}"))?;
+    let file = File::open(archive)?;
+    match format {
+        Format::Zip => visit_zip(file, &mut f),
+        Format::Tar => visit_tar(file, &mut f),
+        Format::TarGz => visit_tar(GzDecoder::new(file), &mut f),
+        Format::TarZst => visit_tar(zstd::Decoder::new(file)?, &mut f),
+    }
+}
+
+fn visit_zip(file: File, f: &mut impl FnMut(Entry, &mut dyn Read) -> Result<bool>) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(file)?;
+    for i in 0..zip.len() {
+        let mut member = zip.by_index(i)?;
+        let Some(path) = member.enclosed_name().as_deref().and_then(enclosed) else {
+            continue;
+        };
+        let kind = if member.is_dir() {
+            FileKind::Dir
+        } else if member.is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::File
+        };
+        let entry = Entry {
+            path,
+            kind,
+            size: member.size(),
+        };
+        if f(entry, &mut member)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn visit_tar(
+    reader: impl Read,
+    f: &mut impl FnMut(Entry, &mut dyn Read) -> Result<bool>,
+) -> Result<()> {
+    use tar::EntryType;
+
+    let mut tar = tar::Archive::new(reader);
+    for member in tar.entries()? {
+        let mut member = member?;
+        let Some(path) = enclosed(&member.path()?) else {
+            continue;
+        };
+        let kind = match member.header().entry_type() {
+            EntryType::Directory => FileKind::Dir,
+            EntryType::Symlink => FileKind::Symlink,
+            EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => FileKind::File,
+            _ => FileKind::Other,
+        };
+        let entry = Entry {
+            path,
+            kind,
+            size: member.size(),
+        };
+        if f(entry, &mut member)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The path without any `./`, or None if it would escape the archive.
+fn enclosed(path: &Path) -> Option<PathBuf> {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => (),
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!clean.as_os_str().is_empty()).then_some(clean)
+}
@@ -5,22 +5,29 @@ use crossterm::event::{KeyCode, KeyModifiers};
 use log::LevelFilter;
 use nucleo::Nucleo;
 use rurt::action::Action;
+use rurt::cleanup::{free_to_threshold, Disposal};
 use rurt::dir_stack::DirStack;
 use rurt::draw::RIGHT_PANE_HIDDEN;
 use rurt::draw::{ViewOpts, PREVIEW_MODE, RIGHT_PANE};
+use rurt::graphics::Protocol;
 use rurt::item::Item;
+use rurt::path_index::PathIndex;
+use rurt::previewers::Previewers;
 use rurt::ratui;
 use rurt::store::Store;
+use rurt::text_index::TextIndex;
 use rurt::tui_log::LogWidgetState;
 use rurt::tui_log::TuiLogger;
-use rurt::walk::{Mode, ReadOpts, Recursion};
+use rurt::walk::{Mode, ReadOpts, Recursion, Source};
 use rurt::App;
 use rurt::ResultOpts;
 use shell_quote::Quote;
 use std::ffi::OsString;
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process::ExitCode;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -33,7 +40,13 @@ enum QuoteFor {
 }
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    after_help = "Audio and video previews need ffprobe, from FFmpeg, on the PATH; \
+without it they're shown as a hex dump."
+)]
 struct Cli {
     #[clap(default_value = ".")]
     start_path: OsString,
@@ -55,11 +68,57 @@ struct Cli {
     #[clap(short, long)]
     mode: Option<Mode>,
 
+    /// where the listing comes from; `tracked` reads the git index
+    #[clap(long, value_enum)]
+    source: Option<Source>,
+
     #[clap(long, value_enum)]
     quote: Option<QuoteFor>,
 
     #[clap(long)]
     force_absolute_path: bool,
+
+    /// refresh the listing as files change
+    #[clap(short, long)]
+    watch: bool,
+
+    /// don't browse; delete the stalest, biggest files under the start path
+    /// until its volume is at most this percent used
+    #[clap(long, value_name = "PERCENT", value_parser = percent)]
+    cleanup_threshold: Option<f64>,
+
+    /// with --cleanup-threshold, only report what would be deleted
+    #[clap(long, requires = "cleanup_threshold")]
+    dry_run: bool,
+
+    /// with --cleanup-threshold, move files to the trash instead of deleting them;
+    /// a trash on the same volume frees nothing until it's emptied
+    #[clap(long, requires = "cleanup_threshold")]
+    trash: bool,
+
+    /// keep a content index of the text files under this directory, to speed up
+    /// content search; may be repeated
+    #[clap(long, value_name = "DIR")]
+    index: Vec<OsString>,
+
+    /// keep a list of every path under this directory, for instant recursive listings
+    /// and the global mode; may be repeated
+    #[clap(long, value_name = "DIR")]
+    path_index: Vec<OsString>,
+
+    /// rules for which previewer to use for what;
+    /// default: previewers.toml in the config dir, if it's there
+    #[clap(long, value_name = "FILE")]
+    previewers: Option<PathBuf>,
+
+    /// the command to play audio and video in the background, with `{}` for the file,
+    /// e.g. "mpv --no-video {}"; default: the file's default application
+    #[clap(long, value_name = "COMMAND")]
+    player: Option<String>,
+
+    /// how to draw images in the preview; default: whatever the terminal looks like it supports
+    #[clap(long, value_enum, value_name = "PROTOCOL")]
+    images: Option<Protocol>,
 }
 
 fn main() -> Result<ExitCode> {
@@ -69,6 +128,31 @@ fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
     let here = fs::canonicalize(cli.start_path).context("start path")?;
 
+    if let Some(threshold) = cli.cleanup_threshold {
+        let disposal = if cli.trash {
+            Disposal::Trash
+        } else {
+            Disposal::Delete
+        };
+        let ok = free_to_threshold(&here, threshold, disposal, cli.dry_run, &mut io::stdout())?;
+        return Ok(if ok {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    let index = index_roots(&cli.index)?.map(TextIndex::start);
+    let paths = index_roots(&cli.path_index)?.map(PathIndex::start);
+
+    let previewers = match cli
+        .previewers
+        .or_else(|| Previewers::default_path().filter(|p| p.exists()))
+    {
+        Some(path) => Previewers::load(&path).with_context(|| format!("previewers in {path:?}"))?,
+        None => Previewers::default(),
+    };
+
     #[rustfmt::skip]
     let bindings = vec![
         (KeyModifiers::NONE, KeyCode::Enter, Action::Activate),
@@ -88,6 +172,17 @@ fn main() -> Result<ExitCode> {
         (KeyModifiers::SHIFT, KeyCode::PageUp, Action::MovePreview(-20)),
         (KeyModifiers::CONTROL, KeyCode::Up, Action::MovePreview(-20)),
         (KeyModifiers::CONTROL, KeyCode::Down, Action::MovePreview(20)),
+        (KeyModifiers::CONTROL, KeyCode::Home, Action::PagePreview(0)),
+        (KeyModifiers::CONTROL, KeyCode::End, Action::PagePreview(100)),
+        (KeyModifiers::ALT, KeyCode::Char('1'), Action::PagePreview(10)),
+        (KeyModifiers::ALT, KeyCode::Char('2'), Action::PagePreview(20)),
+        (KeyModifiers::ALT, KeyCode::Char('3'), Action::PagePreview(30)),
+        (KeyModifiers::ALT, KeyCode::Char('4'), Action::PagePreview(40)),
+        (KeyModifiers::ALT, KeyCode::Char('5'), Action::PagePreview(50)),
+        (KeyModifiers::ALT, KeyCode::Char('6'), Action::PagePreview(60)),
+        (KeyModifiers::ALT, KeyCode::Char('7'), Action::PagePreview(70)),
+        (KeyModifiers::ALT, KeyCode::Char('8'), Action::PagePreview(80)),
+        (KeyModifiers::ALT, KeyCode::Char('9'), Action::PagePreview(90)),
         (KeyModifiers::NONE, KeyCode::Char('\\'), Action::CycleRecursion),
         (KeyModifiers::CONTROL, KeyCode::Char('h'), Action::Up),
         (KeyModifiers::CONTROL, KeyCode::Char('l'), Action::Down),
@@ -97,16 +192,35 @@ fn main() -> Result<ExitCode> {
         (KeyModifiers::ALT | KeyModifiers::SHIFT, KeyCode::Char('A'), Action::CyclePalette),
         (KeyModifiers::CONTROL, KeyCode::Char('a'), Action::CycleHidden,),
         (KeyModifiers::CONTROL, KeyCode::Char('y'), Action::CycleIgnored,),
+        (KeyModifiers::ALT, KeyCode::Char('i'), Action::AddToGitignore),
+        (KeyModifiers::ALT | KeyModifiers::SHIFT, KeyCode::Char('I'), Action::AddExtensionToGitignore),
         (KeyModifiers::CONTROL, KeyCode::Char('f'), Action::CycleModeSkipping(vec![Mode::Mixed])),
         (KeyModifiers::ALT, KeyCode::Char('f'), Action::SetMode(Mode::Mixed)),
         (KeyModifiers::CONTROL, KeyCode::Char('e'), Action::Expand),
         (KeyModifiers::CONTROL, KeyCode::Char('r'), Action::CycleRecursion,),
+        (KeyModifiers::ALT, KeyCode::Char('r'), Action::CycleSource),
+        (KeyModifiers::ALT, KeyCode::Char('w'), Action::ToggleWatch),
+        (KeyModifiers::ALT, KeyCode::Char('d'), Action::ToggleDiskUsage),
+        (KeyModifiers::ALT, KeyCode::Char('s'), Action::CycleSort),
+        (KeyModifiers::ALT, KeyCode::Char('c'), Action::ToggleCleanup),
+        (KeyModifiers::NONE, KeyCode::Tab, Action::ToggleSelect),
+        (KeyModifiers::ALT, KeyCode::Char('x'), Action::RemoveSelected),
+        (KeyModifiers::ALT, KeyCode::Char('e'), Action::Extract),
+        (KeyModifiers::ALT, KeyCode::Char('/'), Action::ToggleGrep),
+        (KeyModifiers::ALT, KeyCode::Char('o'), Action::ToggleGlobal),
         (KeyModifiers::CONTROL, KeyCode::Char('t'), Action::SetTarget),
         (KeyModifiers::CONTROL, KeyCode::Char('g'), Action::Open),
+        (KeyModifiers::ALT, KeyCode::Char('l'), Action::Play),
         (KeyModifiers::CONTROL, KeyCode::Char('p'), Action::TogglePreview),
         (KeyModifiers::ALT, KeyCode::Char('p'), Action::TogglePreviewMode),
         (KeyModifiers::ALT | KeyModifiers::SHIFT, KeyCode::Char('P'), Action::TogglePreviewColour),
+        (KeyModifiers::ALT, KeyCode::Char('m'), Action::TogglePreviewRendered),
         (KeyModifiers::ALT, KeyCode::Char('g'), Action::FocusGit),
+        (KeyModifiers::ALT, KeyCode::Char('v'), Action::FocusPreview),
+        (KeyModifiers::ALT, KeyCode::Char('y'), Action::CopyPreviewLines),
+        (KeyModifiers::ALT, KeyCode::Char('k'), Action::CopyKeyPath),
+        (KeyModifiers::ALT, KeyCode::Char('z'), Action::ToggleFold),
+        (KeyModifiers::ALT | KeyModifiers::SHIFT, KeyCode::Char('E'), Action::EditAtLine),
         (KeyModifiers::CONTROL, KeyCode::Char('o'), Action::DirBack),
         (KeyModifiers::CONTROL, KeyCode::Char('u'), Action::DirForward),
     ];
@@ -115,6 +229,9 @@ fn main() -> Result<ExitCode> {
         dir_stack: DirStack::default(),
         read_opts: ReadOpts {
             target_dir: here.clone(),
+            watch: cli.watch,
+            index,
+            paths,
             ..Default::default()
         },
         view_opts: ViewOpts {
@@ -127,11 +244,14 @@ fn main() -> Result<ExitCode> {
             log_pane: cfg!(feature = "log_pane"),
             git_info: cfg!(feature = "git_info"),
             input_bottom: cfg!(feature = "input_bottom"),
+            graphics: cli.images.unwrap_or_else(Protocol::detect),
         },
         result_opts: ResultOpts {
             force_absolute_path: cli.force_absolute_path,
         },
         bindings,
+        previewers: Arc::new(previewers),
+        player: cli.player,
         here,
     };
 
@@ -147,6 +267,10 @@ fn main() -> Result<ExitCode> {
         app.read_opts.recursion = Recursion::All;
     }
 
+    if let Some(source) = cli.source {
+        app.read_opts.source = source;
+    }
+
     let mut store = Store::new(Nucleo::<Item>::new(
         nucleo::Config::DEFAULT,
         Arc::new(|| {}),
@@ -171,3 +295,25 @@ fn main() -> Result<ExitCode> {
     }
     Ok(code)
 }
+
+fn index_roots(roots: &[OsString]) -> Result<Option<Vec<PathBuf>>> {
+    if roots.is_empty() {
+        return Ok(None);
+    }
+    roots
+        .iter()
+        .map(|root| fs::canonicalize(root).with_context(|| format!("index root {root:?}")))
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// A percentage a volume could be brought down to: above nothing, up to all of it.
+fn percent(s: &str) -> Result<f64, String> {
+    let percent: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    // NaN fails both
+    if percent > 0. && percent <= 100. {
+        Ok(percent)
+    } else {
+        Err("must be more than 0 and at most 100".to_string())
+    }
+}
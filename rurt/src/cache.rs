@@ -40,6 +40,18 @@ where
         entry.value.as_ref()
     }
 
+    /// Work it out again in the background, still giving the old value until it's done.
+    pub fn recompute(&mut self, key: K, f: impl FnOnce() -> Option<V> + Send + 'static) {
+        match self.map.get_mut(&key) {
+            // already on it
+            Some(entry) if entry.handle.is_some() => (),
+            Some(entry) => entry.handle = Some(std::thread::spawn(f)),
+            None => {
+                self.compute(key, f);
+            }
+        }
+    }
+
     pub fn get(&mut self, key: &K) -> Option<&V> {
         let entry = self.map.get_mut(key)?;
         try_complete(entry);
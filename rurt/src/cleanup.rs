@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::du::{disk_bytes, hardlink_id, human_size, DiskUsage};
+use crate::item::Item;
+use anyhow::{bail, Result};
+use ignore::WalkBuilder;
+use log::info;
+
+/// Space on the filesystem holding a path, as `df` reports it.
+#[derive(Copy, Clone, Debug)]
+pub struct Volume {
+    pub total: u64,
+    /// free for unprivileged users
+    pub available: u64,
+    /// free including the root reserve
+    pub free: u64,
+}
+
+impl Volume {
+    #[cfg(unix)]
+    // the field widths differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    pub fn of(path: &Path) -> Result<Self> {
+        use std::ffi::CString;
+        use std::mem::MaybeUninit;
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: c_path is nul terminated, and stat is only read if it was filled in
+        if unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+            bail!("statvfs {path:?}: {}", std::io::Error::last_os_error());
+        }
+        let stat = unsafe { stat.assume_init() };
+        let block = stat.f_frsize as u64;
+        Ok(Self {
+            total: stat.f_blocks as u64 * block,
+            available: stat.f_bavail as u64 * block,
+            free: stat.f_bfree as u64 * block,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn of(path: &Path) -> Result<Self> {
+        bail!("volume usage for {path:?} is only available on unix")
+    }
+
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+
+    /// percentage used, if `freed` more bytes were released; like `df`, the
+    /// root reserve doesn't count as space you could have used
+    pub fn used_percent_after(&self, freed: u64) -> f64 {
+        let usable = self.used() + self.available;
+        if usable == 0 {
+            return 0.;
+        }
+        self.used().saturating_sub(freed) as f64 * 100. / usable as f64
+    }
+
+    pub fn used_percent(&self) -> f64 {
+        self.used_percent_after(0)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Disposal {
+    Trash,
+    Delete,
+}
+
+/// The cleanup mode's state: the volume we're trying to free, and what's been picked.
+pub struct Cleanup {
+    pub volume: Option<Volume>,
+    /// bytes each selected path was using when it was selected
+    pub selected: HashMap<PathBuf, u64>,
+    pub confirming: bool,
+}
+
+impl Cleanup {
+    pub fn new(here: &Path) -> Self {
+        let mut cleanup = Self {
+            volume: None,
+            selected: HashMap::new(),
+            confirming: false,
+        };
+        cleanup.refresh(here);
+        cleanup
+    }
+
+    pub fn refresh(&mut self, here: &Path) {
+        self.volume = Volume::of(here).inspect_err(|e| info!("cleanup: {e}")).ok();
+    }
+
+    pub fn toggle(&mut self, path: PathBuf, bytes: u64) {
+        if self.selected.remove(&path).is_none() {
+            self.selected.insert(path, bytes);
+        }
+    }
+
+    pub fn freed(&self) -> u64 {
+        self.selected.values().sum()
+    }
+
+    /// Trash or delete everything selected; anything which fails stays selected.
+    pub fn dispose(&mut self, disposal: Disposal, here: &Path) -> usize {
+        let mut done = 0;
+        self.selected
+            .retain(|path, _| match dispose(path, disposal) {
+                Ok(()) => {
+                    done += 1;
+                    false
+                }
+                Err(e) => {
+                    info!("cleanup: {path:?}: {e}");
+                    true
+                }
+            });
+        self.refresh(here);
+        done
+    }
+}
+
+fn dispose(path: &Path, disposal: Disposal) -> Result<()> {
+    match disposal {
+        Disposal::Trash => trash::delete(path)?,
+        Disposal::Delete if path.symlink_metadata()?.is_dir() => fs::remove_dir_all(path)?,
+        Disposal::Delete => fs::remove_file(path)?,
+    }
+    Ok(())
+}
+
+/// Bigger and older is a better candidate; a year-old gigabyte beats a fresh one.
+pub fn rank(bytes: u64, modified: Option<SystemTime>, now: SystemTime) -> f64 {
+    let age_days = modified
+        .and_then(|m| now.duration_since(m).ok())
+        .map(|age| age.as_secs_f64() / 86_400.)
+        .unwrap_or_default();
+    bytes as f64 * (1. + age_days)
+}
+
+/// Space an item is using; directories need disk usage running to know.
+pub fn item_bytes(item: &Item, disk_usage: Option<&DiskUsage>) -> u64 {
+    disk_usage
+        .and_then(|du| du.size(item))
+        .map(|size| size.bytes)
+        .or_else(|| item.info()?.stat().map(disk_bytes))
+        .unwrap_or_default()
+}
+
+pub fn item_rank(item: &Item, disk_usage: Option<&DiskUsage>, now: SystemTime) -> f64 {
+    let modified = item
+        .info()
+        .and_then(|info| info.stat())
+        .and_then(|stat| stat.modified().ok());
+    rank(item_bytes(item, disk_usage), modified, now)
+}
+
+struct Candidate {
+    path: PathBuf,
+    bytes: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Files under `root` worth removing, best first. Hidden files (and so `.git`)
+/// are left alone, as are other filesystems and hardlinked files, which
+/// wouldn't free anything.
+fn candidates(root: &Path) -> Vec<Candidate> {
+    let now = SystemTime::now();
+    let mut found = WalkBuilder::new(root)
+        .standard_filters(false)
+        .hidden(true)
+        .follow_links(false)
+        .same_file_system(true)
+        .build()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() || hardlink_id(&metadata).is_some() {
+                return None;
+            }
+            Some(Candidate {
+                path: entry.into_path(),
+                bytes: disk_bytes(&metadata),
+                modified: metadata.modified().ok(),
+            })
+        })
+        .collect::<Vec<_>>();
+    found.sort_by(|a, b| rank(b.bytes, b.modified, now).total_cmp(&rank(a.bytes, a.modified, now)));
+    found
+}
+
+/// Remove the best candidates under `root` until its volume is at most
+/// `threshold` percent used, reporting to `out`. Nothing is removed if everything
+/// under `root` wouldn't be enough, as when something else fills the volume.
+/// Returns false if anything couldn't be removed, or there wasn't enough to remove.
+pub fn free_to_threshold(
+    root: &Path,
+    threshold: f64,
+    disposal: Disposal,
+    dry_run: bool,
+    out: &mut impl Write,
+) -> Result<bool> {
+    let volume = Volume::of(root)?;
+    writeln!(
+        out,
+        "{}: {:.1}% of {} used, threshold {threshold}%",
+        root.display(),
+        volume.used_percent(),
+        human_size(volume.total),
+    )?;
+    if volume.used_percent() <= threshold {
+        writeln!(out, "nothing to do")?;
+        return Ok(true);
+    }
+
+    let candidates = candidates(root);
+    let freeable = candidates.iter().map(|c| c.bytes).sum();
+    if volume.used_percent_after(freeable) > threshold {
+        writeln!(
+            out,
+            "removing everything here would only free {} ({:.1}% used); not removing anything",
+            human_size(freeable),
+            volume.used_percent_after(freeable),
+        )?;
+        return Ok(false);
+    }
+
+    let now = SystemTime::now();
+    let mut freed = 0;
+    let mut ok = true;
+    for candidate in candidates {
+        if volume.used_percent_after(freed) <= threshold {
+            break;
+        }
+        let age_days = candidate
+            .modified
+            .and_then(|m| now.duration_since(m).ok())
+            .map(|age| age.as_secs() / 86_400)
+            .unwrap_or_default();
+        let result = if dry_run {
+            Ok(())
+        } else {
+            dispose(&candidate.path, disposal)
+        };
+        match result {
+            Ok(()) => {
+                freed += candidate.bytes;
+                writeln!(
+                    out,
+                    "{:>7} {age_days:>5}d {}",
+                    human_size(candidate.bytes),
+                    candidate.path.display()
+                )?;
+            }
+            Err(e) => {
+                ok = false;
+                writeln!(out, "failed: {}: {e}", candidate.path.display())?;
+            }
+        }
+    }
+
+    let after = volume.used_percent_after(freed);
+    writeln!(
+        out,
+        "{} {} ({:.1}% used)",
+        if dry_run { "would free" } else { "freed" },
+        human_size(freed),
+        after,
+    )?;
+    if after > threshold {
+        writeln!(out, "not enough to remove to get under {threshold}%")?;
+        ok = false;
+    }
+    Ok(ok)
+}
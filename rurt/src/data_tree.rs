@@ -0,0 +1,479 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use ratatui::prelude::*;
+use serde::de::{self, Deserialize, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess};
+
+use crate::preview::preview_header;
+
+/// how toml hands over dates, through serde
+const TOML_DATETIME: &str = "$__toml_private_datetime";
+
+/// in characters; there's no seeing past the edge of the pane anyway
+const LONGEST_VALUE: usize = 500;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        Some(match ext.as_str() {
+            "json" => Format::Json,
+            "toml" => Format::Toml,
+            "yaml" | "yml" => Format::Yaml,
+            _ => return None,
+        })
+    }
+}
+
+/// A parsed document, flattened, and shown as a tree with some of it folded away.
+pub struct Tree {
+    showing: PathBuf,
+    nodes: Vec<Node>,
+    folded: HashSet<usize>,
+    /// the node on each line of the render, after the header
+    rows: Vec<usize>,
+    height: usize,
+    /// only the start of the file was parsed
+    truncated: bool,
+}
+
+struct Node {
+    parent: Option<usize>,
+    depth: usize,
+    key: Key,
+    kind: Kind,
+    /// one past the last node under this one
+    end: usize,
+}
+
+enum Key {
+    Root,
+    Field(String),
+    Index(usize),
+}
+
+enum Kind {
+    Map(usize),
+    List(usize),
+    Scalar(Scalar, String),
+}
+
+#[derive(Copy, Clone)]
+enum Scalar {
+    String,
+    Number,
+    Bool,
+    Null,
+    Date,
+}
+
+/// Where parsing gave up, and why
+pub struct ParseError {
+    message: String,
+    /// line and column, from one
+    position: Option<(usize, usize)>,
+}
+
+impl Tree {
+    /// `complete` is false if `content` is only the start of the file; we make
+    /// the best of what's there.
+    pub fn parse(
+        format: Format,
+        content: &[u8],
+        complete: bool,
+        showing: &Path,
+        height: usize,
+    ) -> Result<Self, ParseError> {
+        let text = String::from_utf8_lossy(content);
+        let text = match (complete, format) {
+            (true, _) => text.into_owned(),
+            (false, Format::Json) => close_truncated(&text),
+            // lines are mostly whole statements
+            (false, _) => text[..text.rfind('\n').unwrap_or(0)].to_string(),
+        };
+        let value = match format {
+            Format::Json => serde_json::from_str(&text).map_err(|e| ParseError {
+                position: Some((e.line(), e.column())),
+                message: e.to_string(),
+            }),
+            Format::Toml => toml::from_str(&text).map_err(|e| ParseError {
+                position: e.span().map(|span| line_column(&text, span.start)),
+                message: e.message().to_string(),
+            }),
+            Format::Yaml => serde_yaml::from_str(&text).map_err(|e| ParseError {
+                position: e.location().map(|at| (at.line(), at.column())),
+                message: e.to_string(),
+            }),
+        }?;
+
+        let mut nodes = Vec::new();
+        flatten(&mut nodes, value, None, 0, Key::Root);
+        Ok(Self {
+            showing: showing.to_path_buf(),
+            nodes,
+            folded: HashSet::new(),
+            rows: Vec::new(),
+            height,
+            truncated: !complete,
+        })
+    }
+
+    pub fn render(&mut self) -> Text<'static> {
+        let mut lines = vec![preview_header("tree", &self.showing)];
+        self.rows.clear();
+        let mut i = 0;
+        while i < self.nodes.len() && self.rows.len() < self.height {
+            self.rows.push(i);
+            let folded = self.folded.contains(&i);
+            lines.push(self.nodes[i].line(folded));
+            i = if folded { self.nodes[i].end } else { i + 1 };
+        }
+        if self.truncated {
+            lines.push(Line::styled(
+                "… only the start of the file is shown",
+                Style::new().dark_gray(),
+            ));
+        }
+        Text::from(lines)
+    }
+
+    /// Fold, or unfold, whatever's on that line of the render; false if there's nothing to fold.
+    pub fn toggle(&mut self, line: usize) -> bool {
+        // the header stands for the whole thing
+        let Some(&node) = self.rows.get(line.saturating_sub(1)) else {
+            return false;
+        };
+        if self.nodes[node].end == node + 1 {
+            return false;
+        }
+        if !self.folded.remove(&node) {
+            self.folded.insert(node);
+        }
+        true
+    }
+
+    /// The key path to whatever's on that line of the render, like `.foo[3].bar`.
+    pub fn path(&self, line: usize) -> Option<String> {
+        let mut node = *self.rows.get(line.saturating_sub(1))?;
+        let mut keys = Vec::new();
+        loop {
+            let n = &self.nodes[node];
+            match &n.key {
+                Key::Root => break,
+                Key::Field(name) if is_identifier(name) => keys.push(format!(".{name}")),
+                Key::Field(name) => keys.push(format!("[{name:?}]")),
+                Key::Index(i) => keys.push(format!("[{i}]")),
+            }
+            node = n.parent?;
+        }
+        keys.reverse();
+        let path = keys.concat();
+        Some(if path.starts_with('.') {
+            path
+        } else {
+            format!(".{path}")
+        })
+    }
+}
+
+impl Node {
+    fn line(&self, folded: bool) -> Line<'static> {
+        let mut spans = vec![Span::raw("  ".repeat(self.depth))];
+        let container = matches!(self.kind, Kind::Map(_) | Kind::List(_));
+        spans.push(Span::styled(
+            match (container, folded) {
+                (false, _) => "  ",
+                (true, false) => "▾ ",
+                (true, true) => "▸ ",
+            },
+            Style::new().dark_gray(),
+        ));
+        match &self.key {
+            Key::Root => {}
+            Key::Field(name) => {
+                spans.push(Span::styled(name.clone(), Style::new().light_blue()));
+                spans.push(Span::raw(": "));
+            }
+            Key::Index(i) => {
+                spans.push(Span::styled(format!("[{i}]"), Style::new().dark_gray()));
+                spans.push(Span::raw(" "));
+            }
+        }
+        let dim = Style::new().dark_gray();
+        match &self.kind {
+            Kind::Map(len) => {
+                spans.push(Span::raw("{…}"));
+                spans.push(Span::styled(format!(" {len} {}", plural(*len, "key")), dim));
+            }
+            Kind::List(len) => {
+                spans.push(Span::raw("[…]"));
+                spans.push(Span::styled(
+                    format!(" {len} {}", plural(*len, "item")),
+                    dim,
+                ));
+            }
+            Kind::Scalar(scalar, text) => {
+                let (style, name) = match scalar {
+                    Scalar::String => (Style::new().green(), "string"),
+                    Scalar::Number => (Style::new().cyan(), "number"),
+                    Scalar::Bool => (Style::new().yellow(), "bool"),
+                    Scalar::Null => (Style::new().dark_gray(), "null"),
+                    Scalar::Date => (Style::new().magenta(), "date"),
+                };
+                let mut text = match scalar {
+                    Scalar::String => format!("{text:?}"),
+                    _ => text.clone(),
+                };
+                if let Some((cut, _)) = text.char_indices().nth(LONGEST_VALUE) {
+                    text.truncate(cut);
+                    text.push('…');
+                }
+                spans.push(Span::styled(text, style));
+                spans.push(Span::styled(format!("  {name}"), dim));
+            }
+        }
+        Line::from(spans)
+    }
+}
+
+impl ParseError {
+    /// The error, and where in the file it is.
+    pub fn render(&self, content: &[u8], showing: &Path) -> Text<'static> {
+        let mut lines = vec![preview_header("tree", showing)];
+        // toml's go over a few lines
+        for (i, message) in self.message.lines().enumerate() {
+            let message = if i == 0 {
+                format!("error: {message}")
+            } else {
+                message.to_string()
+            };
+            lines.push(Line::styled(message, Style::new().light_red()));
+        }
+        let Some((line, column)) = self.position else {
+            return Text::from(lines);
+        };
+        lines.push(Line::styled(
+            format!("at line {line}, column {column}:"),
+            Style::new().dark_gray(),
+        ));
+        let text = String::from_utf8_lossy(content);
+        let from = line.saturating_sub(3);
+        for (i, source) in text.lines().enumerate().skip(from).take(line - from) {
+            lines.push(Line::from(vec![
+                Span::styled(format!("{:>5} ", i + 1), Style::new().dark_gray()),
+                Span::raw(source.replace('\t', "  ")),
+            ]));
+        }
+        lines.push(Line::styled(
+            format!("{}^", " ".repeat(6 + column.saturating_sub(1))),
+            Style::new().light_red(),
+        ));
+        Text::from(lines)
+    }
+}
+
+fn flatten(nodes: &mut Vec<Node>, value: Value, parent: Option<usize>, depth: usize, key: Key) {
+    let index = nodes.len();
+    let (kind, children) = match value {
+        Value::Map(entries) => (
+            Kind::Map(entries.len()),
+            entries
+                .into_iter()
+                .map(|(name, value)| (Key::Field(name), value))
+                .collect(),
+        ),
+        Value::List(items) => (
+            Kind::List(items.len()),
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| (Key::Index(i), value))
+                .collect(),
+        ),
+        Value::Scalar(scalar, text) => (Kind::Scalar(scalar, text), Vec::new()),
+    };
+    nodes.push(Node {
+        parent,
+        depth,
+        key,
+        kind,
+        end: index + 1,
+    });
+    for (key, value) in children {
+        flatten(nodes, value, Some(index), depth + 1, key);
+    }
+    nodes[index].end = nodes.len();
+}
+
+/// Cut a truncated JSON document back to the last whole value, and close what's open.
+fn close_truncated(text: &str) -> String {
+    let mut cut = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, b) in text.bytes().enumerate() {
+        match (in_string, b) {
+            (true, _) if escaped => escaped = false,
+            (true, b'\\') => escaped = true,
+            (true, b'"') => in_string = false,
+            (true, _) => {}
+            (false, b'"') => in_string = true,
+            (false, b'{' | b'[' | b'}' | b']') => cut = i + 1,
+            (false, b',') => cut = i,
+            _ => {}
+        }
+    }
+
+    let mut closed = text[..cut].to_string();
+    let mut open = Vec::new();
+    in_string = false;
+    for b in closed.bytes() {
+        match (in_string, b) {
+            (true, _) if escaped => escaped = false,
+            (true, b'\\') => escaped = true,
+            (true, b'"') => in_string = false,
+            (true, _) => {}
+            (false, b'"') => in_string = true,
+            (false, b'{') => open.push('}'),
+            (false, b'[') => open.push(']'),
+            (false, b'}' | b']') => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    closed.extend(open.into_iter().rev());
+    closed
+}
+
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn plural(n: usize, word: &str) -> String {
+    if n == 1 {
+        word.to_string()
+    } else {
+        format!("{word}s")
+    }
+}
+
+/// Any of the formats, keeping maps in the order they're written.
+enum Value {
+    Map(Vec<(String, Value)>),
+    List(Vec<Value>),
+    Scalar(Scalar, String),
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> de::Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Scalar(Scalar::Bool, v.to_string()))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Scalar(Scalar::Number, v.to_string()))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::Scalar(Scalar::Number, v.to_string()))
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<Value, E> {
+        Ok(Value::Scalar(Scalar::Number, v.to_string()))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Value, E> {
+        Ok(Value::Scalar(Scalar::Number, v.to_string()))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Scalar(Scalar::Number, v.to_string()))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Scalar(Scalar::String, v.to_string()))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Scalar(Scalar::Null, "null".to_string()))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        self.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::List(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = Vec::new();
+        while let Some((key, value)) = map.next_entry::<Value, Value>()? {
+            entries.push((key.into_key(), value));
+        }
+        Ok(match entries.as_mut_slice() {
+            [(key, Value::Scalar(Scalar::String, text))] if key == TOML_DATETIME => {
+                Value::Scalar(Scalar::Date, mem::take(text))
+            }
+            _ => Value::Map(entries),
+        })
+    }
+
+    /// yaml's `!tags`
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        let (tag, variant) = data.variant::<String>()?;
+        let value = variant.newtype_variant()?;
+        Ok(Value::Map(vec![(format!("!{tag}"), value)]))
+    }
+}
+
+impl Value {
+    /// keys are usually strings, but yaml allows anything
+    fn into_key(self) -> String {
+        match self {
+            Value::Scalar(_, text) => text,
+            Value::Map(_) => "{…}".to_string(),
+            Value::List(_) => "[…]".to_string(),
+        }
+    }
+}
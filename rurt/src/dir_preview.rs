@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use lscolors::{LsColors, Style as LsStyle};
+use ratatui::prelude::*;
+
+use crate::du::human_size;
+use crate::git::Letter;
+use crate::preview::{preview_header, PreviewStyle};
+use crate::walk::{walk_builder, ReadOpts};
+
+/// How many levels of the tree are shown
+const DEPTH: usize = 3;
+
+/// Children shown for each directory; the rest are only counted
+const PER_DIR: usize = 12;
+
+/// Lines of tree, so the README isn't pushed too far down
+const TREE_LINES: usize = 40;
+
+/// Entries walked for the totals, before giving up on them being exact; this is every
+/// time the cursor lands on a directory, so it's kept to a few moments' work
+const COUNT_LIMIT: usize = 10_000;
+
+/// What the listing knows, which the directory preview should agree with
+#[derive(Default)]
+pub struct Listing {
+    pub ls_colors: LsColors,
+    /// changed files, by absolute path
+    pub changes: Vec<(PathBuf, Letter)>,
+}
+
+struct Entry {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+    style: Style,
+}
+
+/// Everything under the directory, as far down as the tree goes
+struct Walked<'l> {
+    children: HashMap<PathBuf, Vec<Entry>>,
+    listing: &'l Listing,
+}
+
+/// A tree of the directory, a few levels deep, under its totals; and the README,
+/// if there is one, for the caller to show too. Walks with the listing's hidden
+/// and ignored settings; `None` if cancelled.
+pub fn render(
+    dir: &Path,
+    style: PreviewStyle,
+    listing: &Listing,
+    cancelled: &AtomicBool,
+) -> Option<(Text<'static>, Option<PathBuf>)> {
+    let read_opts = ReadOpts {
+        show_hidden: style.hidden,
+        show_ignored: style.ignored,
+        ..ReadOpts::default()
+    };
+    let walk = walk_builder(dir, &read_opts).build();
+
+    let mut walked = Walked {
+        children: HashMap::new(),
+        listing,
+    };
+    let (mut files, mut dirs, mut bytes) = (0usize, 0usize, 0u64);
+    let mut complete = true;
+    for (i, entry) in walk.enumerate() {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        if i > COUNT_LIMIT {
+            complete = false;
+            break;
+        }
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if entry.depth() == 0 {
+            continue;
+        }
+        let metadata = entry.metadata().ok();
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        if is_dir {
+            dirs += 1;
+        } else {
+            files += 1;
+            bytes += metadata.as_ref().map_or(0, |m| m.len());
+        }
+        if entry.depth() > DEPTH {
+            continue;
+        }
+        let Some(parent) = entry.path().parent() else {
+            continue;
+        };
+        let style = listing
+            .ls_colors
+            .style_for_path_with_metadata(entry.path(), metadata.as_ref())
+            .filter(|_| style.coloured)
+            .map(|s| Style::from(LsStyle::to_crossterm_style(s)))
+            .unwrap_or_default();
+        walked
+            .children
+            .entry(parent.to_path_buf())
+            .or_default()
+            .push(Entry {
+                path: entry.path().to_path_buf(),
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir,
+                style,
+            });
+    }
+    for children in walked.children.values_mut() {
+        children.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    }
+
+    let dim = Style::new().dark_gray();
+    let mut lines = vec![
+        preview_header("tree", dir),
+        Line::styled(
+            format!(
+                "{dirs}{more} {}, {files}{more} {}, {}{more}",
+                if dirs == 1 { "dir" } else { "dirs" },
+                if files == 1 { "file" } else { "files" },
+                human_size(bytes),
+                more = if complete { "" } else { "+" },
+            ),
+            dim,
+        ),
+        Line::styled(".", dim),
+    ];
+    let mut tree = Vec::new();
+    walked.tree(dir, "", &mut tree);
+    if tree.len() > TREE_LINES {
+        tree.truncate(TREE_LINES);
+        tree.push(Line::styled("…", dim));
+    }
+    lines.extend(tree);
+
+    let readme = walked.children.get(dir).and_then(|children| {
+        children
+            .iter()
+            .filter(|c| !c.is_dir && c.name.to_ascii_lowercase().starts_with("readme"))
+            // README.md over README.txt, say
+            .min_by_key(|c| c.name.len())
+            .map(|c| c.path.clone())
+    });
+    Some((Text::from(lines), readme))
+}
+
+impl Walked<'_> {
+    fn tree(&self, dir: &Path, prefix: &str, lines: &mut Vec<Line<'static>>) {
+        let Some(children) = self.children.get(dir) else {
+            return;
+        };
+        let dim = Style::new().dark_gray();
+        let hidden = children.len().saturating_sub(PER_DIR);
+        for (i, child) in children.iter().take(PER_DIR).enumerate() {
+            let last = hidden == 0 && i + 1 == children.len();
+            let mut spans = vec![
+                Span::styled(prefix.to_string(), dim),
+                Span::styled(if last { "└── " } else { "├── " }, dim),
+                Span::styled(child.name.clone(), child.style),
+            ];
+            if child.is_dir {
+                spans.push(Span::styled("/", child.style));
+            }
+            if let Some(marker) = self.marker(child) {
+                spans.push(Span::styled(format!(" {marker}"), dim));
+            }
+            lines.push(Line::from(spans));
+
+            if child.is_dir {
+                let prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+                self.tree(&child.path, &prefix, lines);
+            }
+        }
+        if hidden > 0 {
+            lines.push(Line::styled(format!("{prefix}└── … {hidden} more"), dim));
+        }
+    }
+
+    /// The git status of a file, or how many files have changed under a directory.
+    fn marker(&self, entry: &Entry) -> Option<String> {
+        let changes = &self.listing.changes;
+        if !entry.is_dir {
+            let (_, letter) = changes.iter().find(|(path, _)| *path == entry.path)?;
+            return Some(format!("[{letter:?}]"));
+        }
+        let changed = changes
+            .iter()
+            .filter(|(path, _)| path.starts_with(&entry.path))
+            .count();
+        (changed > 0).then(|| format!("[{changed} changed]"))
+    }
+}
@@ -1,14 +1,22 @@
+use crate::cleanup::Cleanup;
 use crate::draw::RightPane::{Hidden, InteractiveGitLog, Preview};
-use crate::git::Git;
+use crate::du::{human_size, Size};
+use crate::git::{Git, Head, RepoSummary};
 use crate::git_but_bad::git_log_matches;
+use crate::graphics::{Placement, Protocol};
 use crate::item::{Item, ItemView, Styling, ViewContext};
 use crate::preview::{preview_header, PreviewCommand};
 use crate::snapped::Snapped;
 use crate::tui_log::{LogWidget, LogWidgetState};
-use crate::ui_state::{matching_preview, CommandPalette, URect, Ui};
-use crate::walk::Recursion;
+use crate::ui_state::{
+    matching_preview, preview_hits, preview_selection, preview_source_line, CommandPalette,
+    SortOrder, URect, Ui,
+};
+use crate::walk::{ReadOpts, Recursion, Source, MODES};
 use crate::{filter_bindings, App, Binding};
 use crossterm::event::KeyModifiers;
+use grep_matcher::Matcher;
+use grep_regex::RegexMatcher;
 use ratatui::layout::{Constraint, Direction, Flex, Layout, Rect};
 use ratatui::prelude::{Line, Span, Style, Stylize, Text};
 use ratatui::style::Color;
@@ -29,26 +37,35 @@ pub enum RightPane {
 #[derive(Copy, Clone, PartialEq)]
 pub enum PreviewMode {
     Content,
+    /// data files, parsed
+    Tree,
     GitLg,
     GitShow,
+    WhyIgnored,
+    Follow,
 }
 
 pub const RIGHT_PANE: [RightPane; 3] = [Preview, Hidden, InteractiveGitLog];
 pub const RIGHT_PANE_HIDDEN: [RightPane; 3] = [Hidden, Preview, InteractiveGitLog];
 
-pub const PREVIEW_MODE: [PreviewMode; 3] = [
+pub const PREVIEW_MODE: [PreviewMode; 6] = [
     PreviewMode::Content,
+    PreviewMode::Tree,
     PreviewMode::GitLg,
     PreviewMode::GitShow,
+    PreviewMode::WhyIgnored,
+    PreviewMode::Follow,
 ];
 
 #[derive(Copy, Clone)]
 pub struct ViewOpts {
     pub right_pane_mode: [RightPane; 3],
-    pub preview_mode_flag: [PreviewMode; 3],
+    pub preview_mode_flag: [PreviewMode; 6],
     pub log_pane: bool,
     pub git_info: bool,
     pub input_bottom: bool,
+    /// how images are drawn in the preview
+    pub graphics: Protocol,
 }
 
 impl ViewOpts {
@@ -179,20 +196,23 @@ pub fn draw_ui(
     log_state: Arc<Mutex<LogWidgetState>>,
 ) {
     draw_input_line(f, &ui.prompt, &ui.input, area.input_line);
-    draw_info_line(f, ui, snap, area.info_line);
-    draw_listing(
-        f,
-        ui,
-        snap,
-        area.main_pane,
-        app.read_opts.recursion != Recursion::None,
-    );
+    draw_info_line(f, ui, &app.read_opts, snap, area.info_line);
+    draw_listing(f, ui, snap, area.main_pane, &app.here, &app.read_opts);
     draw_right_pane(f, area, ui, app);
 
     if ui.command_palette.showing {
         draw_palette(f, &ui.command_palette, &app.bindings, area.main_area);
     }
 
+    if let Some(cleanup) = ui.cleanup.as_ref().filter(|c| c.confirming) {
+        draw_cleanup_confirm(f, cleanup, area.main_area);
+    }
+
+    if ui.command_palette.showing || ui.cleanup.as_ref().is_some_and(|c| c.confirming) {
+        // the terminal would draw the image over the top of them
+        ui.placement.replace(None);
+    }
+
     if !area.log.is_empty() {
         if let Ok(log_state) = &mut log_state.lock() {
             f.render_widget(Block::new().borders(Borders::ALL), area.log);
@@ -207,7 +227,17 @@ fn draw_right_pane(f: &mut Frame<'_>, area: Areas, ui: &Ui, app: &App) {
         RightPane::Hidden => (),
         RightPane::Preview => {
             draw_divider(f, area.divider);
-            draw_preview(f, ui, app.view_opts.preview_mode(), area.side_pane);
+            let mode = app.view_opts.preview_mode();
+            let searching = ui.preview_focus.focus || !ui.preview_focus.input.value().is_empty();
+            if searching {
+                let [search, preview] =
+                    Layout::vertical([Constraint::Length(1), Constraint::Percentage(100)])
+                        .areas(area.side_pane);
+                draw_preview_focus(f, ui, mode, search);
+                draw_preview(f, ui, mode, preview);
+            } else {
+                draw_preview(f, ui, mode, area.side_pane);
+            }
         }
         RightPane::InteractiveGitLog => {
             draw_divider(f, area.divider);
@@ -243,6 +273,45 @@ fn draw_palette(f: &mut Frame, palette: &CommandPalette, bindings: &[Binding], a
     f.render_widget(Text::from(lines), area);
 }
 
+fn draw_cleanup_confirm(f: &mut Frame, cleanup: &Cleanup, area: Rect) {
+    let block = Block::bordered().title("cleanup");
+    let area = popup_area(area, 60, 40);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    let area = edge_inset(area, 1);
+
+    let freed = cleanup.freed();
+    let mut lines = vec![Line::raw(format!(
+        "remove {} paths, {}?",
+        cleanup.selected.len(),
+        human_size(freed)
+    ))];
+    if let Some(volume) = cleanup.volume {
+        lines.push(Line::raw(format!(
+            "{:.1}% -> {:.1}% used",
+            volume.used_percent(),
+            volume.used_percent_after(freed)
+        )));
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(
+        "t: move to trash (frees nothing until it's emptied)",
+    ));
+    lines.push(Line::raw("D: delete permanently").light_red());
+    lines.push(Line::raw("anything else: cancel"));
+    lines.push(Line::raw(""));
+
+    let mut selected = cleanup.selected.iter().collect::<Vec<_>>();
+    selected.sort_unstable_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    lines.extend(
+        selected.into_iter().map(|(path, bytes)| {
+            Line::raw(format!("{:>7} {}", human_size(*bytes), path.display()))
+        }),
+    );
+
+    f.render_widget(Text::from(lines), area);
+}
+
 fn render_mods(mods: KeyModifiers) -> String {
     let mut out = String::with_capacity(3);
     if mods.contains(KeyModifiers::CONTROL) {
@@ -267,7 +336,15 @@ fn edge_inset(area: Rect, margin: u16) -> Rect {
     inset_area
 }
 
-fn draw_listing(f: &mut Frame, ui: &Ui, snap: &Snapped, area: Rect, recursive_listing: bool) {
+fn draw_listing(
+    f: &mut Frame,
+    ui: &Ui,
+    snap: &Snapped,
+    area: Rect,
+    here: &Path,
+    read_opts: &ReadOpts,
+) {
+    let recursive_listing = read_opts.recursion != Recursion::None;
     let mut columns = Columns::default();
     let searching = ui.is_searching();
 
@@ -281,9 +358,19 @@ fn draw_listing(f: &mut Frame, ui: &Ui, snap: &Snapped, area: Rect, recursive_li
         .take(usize::from(area.height).saturating_sub(STATUS_LINES))
     {
         let rot = compute_rot(searching, i);
-        let view = render_item(item, &ui.git_info, &styling, rot);
-
-        let selected = ui.cursor_showing.as_ref() == Some(&item);
+        let ignored = read_opts.show_ignored
+            && item
+                .path()
+                .zip(item.info())
+                .and_then(|(path, info)| ui.ignores.reason(path, info.kind.is_dir()))
+                .is_some();
+        let size = ui
+            .disk_usage
+            .as_ref()
+            .and_then(|du| du.size(item).map(|size| (size, du.proportion(size))));
+        let view = render_item(item, &ui.git_info, ignored, size, &styling, rot);
+
+        let selected = ui.cursor_showing.as_ref() == Some(item);
 
         let current_indicator = if selected {
             Span::styled("> ", Style::new().light_red())
@@ -291,6 +378,21 @@ fn draw_listing(f: &mut Frame, ui: &Ui, snap: &Snapped, area: Rect, recursive_li
             Span::raw("  ")
         };
 
+        let marked = item.path().map(|path| here.join(path)).is_some_and(|path| {
+            ui.marked.contains(&path)
+                || ui
+                    .cleanup
+                    .as_ref()
+                    .is_some_and(|cleanup| cleanup.selected.contains_key(&path))
+        });
+
+        // the selection is only marked against the name
+        let leading_indicator = match (selected, marked) {
+            (true, true) => Span::styled(">*", Style::new().light_red()),
+            (false, true) => Span::styled(" *", Style::new().light_yellow()),
+            _ => current_indicator.clone(),
+        };
+
         let current_indicator_right = if selected {
             Span::styled(" <", Style::new().light_red())
         } else {
@@ -300,18 +402,18 @@ fn draw_listing(f: &mut Frame, ui: &Ui, snap: &Snapped, area: Rect, recursive_li
         let mut entry = ColumnEntry::default();
 
         if recursive_listing {
-            entry.short.push(current_indicator.clone());
+            entry.short.push(leading_indicator.clone());
             entry.short.extend(view.short);
             entry.short.push(current_indicator_right.clone());
 
-            if view.primary.len() > 0 {
+            if !view.primary.is_empty() {
                 entry.primary.push(Span::raw("  |"));
                 entry.primary.push(current_indicator.clone());
                 entry.primary.extend(view.primary);
                 entry.primary.push(current_indicator_right.clone());
             }
         } else {
-            entry.primary.push(current_indicator.clone());
+            entry.primary.push(leading_indicator);
             entry.primary.extend(view.primary);
             entry.primary.push(current_indicator_right.clone());
         }
@@ -336,7 +438,14 @@ fn draw_listing(f: &mut Frame, ui: &Ui, snap: &Snapped, area: Rect, recursive_li
     display_columns(f, area, columns, recursive_listing)
 }
 
-fn render_item<'a>(item: &'a Item, git: &Option<Git>, styling: &Styling, rot: f32) -> ItemView<'a> {
+fn render_item<'a>(
+    item: &'a Item,
+    git: &Option<Git>,
+    ignored: bool,
+    size: Option<(Size, f32)>,
+    styling: &Styling,
+    rot: f32,
+) -> ItemView<'a> {
     let (git_status, git_info) = (|| {
         let path = item.path()?;
         let git = git.as_ref()?;
@@ -346,9 +455,11 @@ fn render_item<'a>(item: &'a Item, git: &Option<Git>, styling: &Styling, rot: f3
 
     let context = ViewContext {
         git_status,
+        ignored,
+        size,
         git_info,
         rot,
-        styling: &styling,
+        styling,
     };
 
     item.render(&context)
@@ -407,20 +518,103 @@ fn draw_input_line(f: &mut Frame, prompt: &str, input: &Input, input_line_area:
     ));
 }
 
-fn draw_info_line(f: &mut Frame, ui: &Ui, snap: &Snapped, area: Rect) {
-    let line = Line::styled(
-        format!(
-            "{}/{} {}",
-            snap.matched,
-            snap.total,
-            if ui.active { "S" } else { " " },
-        ),
-        Style::new().fg(Color::Indexed(250)),
-    );
+fn draw_info_line(f: &mut Frame, ui: &Ui, read_opts: &ReadOpts, snap: &Snapped, area: Rect) {
+    let scanning = ui.active
+        || ui
+            .disk_usage
+            .as_ref()
+            .map(|du| du.is_scanning())
+            .unwrap_or_default();
+    let flag = |on: bool, c: char| if on { c } else { '-' };
+    let mut spans = vec![Span::raw(format!(
+        "{}/{} {} {} {}{}{}{}",
+        snap.matched,
+        snap.total,
+        if scanning { "S" } else { " " },
+        MODES[read_opts.mode_index].name(),
+        flag(read_opts.recursion != Recursion::None, 'R'),
+        flag(read_opts.show_hidden, 'H'),
+        flag(read_opts.show_ignored, 'I'),
+        flag(read_opts.watch, 'W'),
+    ))];
+
+    if read_opts.source != Source::Walk {
+        spans.push(Span::raw(format!(" {}", read_opts.source.name())));
+    }
+
+    if ui.grep {
+        spans.push(Span::raw(" grep"));
+    }
+
+    if read_opts.global {
+        spans.push(Span::raw(" global"));
+    }
+
+    if ui.disk_usage.is_some() {
+        spans.push(Span::raw(" du"));
+    }
+
+    if ui.sort != SortOrder::Name {
+        spans.push(Span::raw(format!(" by {}", ui.sort.name())));
+    }
+
+    if let Some(cleanup) = &ui.cleanup {
+        spans.push(Span::raw(" cleanup"));
+        if let Some(volume) = cleanup.volume {
+            spans.push(Span::raw(format!(" {:.1}%", volume.used_percent())));
+            if !cleanup.selected.is_empty() {
+                let freed = cleanup.freed();
+                spans.push(Span::styled(
+                    format!(
+                        " -> {:.1}% ({} selected, {})",
+                        volume.used_percent_after(freed),
+                        cleanup.selected.len(),
+                        human_size(freed)
+                    ),
+                    Style::new().light_yellow(),
+                ));
+            }
+        }
+    }
+
+    if let Some(summary) = ui.git_info.as_ref().and_then(|git| git.summary()) {
+        spans.push(Span::raw("  "));
+        spans.extend(repo_summary_spans(&summary));
+    }
+
+    let line = Line::from(spans).style(Style::new().fg(Color::Indexed(250)));
 
     f.render_widget(line, area);
 }
 
+fn repo_summary_spans(summary: &RepoSummary) -> Vec<Span<'static>> {
+    let mut spans = vec![match &summary.head {
+        Head::Branch(name) => Span::styled(name.to_string(), Style::new().light_green()),
+        Head::Detached(id) => Span::styled(format!("({id})"), Style::new().light_yellow()),
+        Head::Unborn => Span::styled("(unborn)", Style::new().light_yellow()),
+    }];
+
+    if let Some((ahead, behind)) = summary.ahead_behind {
+        if ahead > 0 {
+            spans.push(Span::raw(format!(" \u{2191}{ahead}"))); // ↑
+        }
+        if behind > 0 {
+            spans.push(Span::raw(format!(" \u{2193}{behind}"))); // ↓
+        }
+    }
+
+    if summary.stashes > 0 {
+        spans.push(Span::raw(format!(" \u{2261}{}", summary.stashes))); // ≡
+    }
+
+    if let Some(in_progress) = summary.in_progress {
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(in_progress, Style::new().light_red().bold()));
+    }
+
+    spans
+}
+
 fn draw_divider(f: &mut Frame, divider_area: Rect) {
     assert_eq!(divider_area.width, 1);
     for y in divider_area.y..divider_area.bottom() {
@@ -442,6 +636,27 @@ fn draw_preview(f: &mut Frame, ui: &Ui, mode: PreviewMode, area: Rect) {
 
     let data = preview.data.lock().expect("panic");
 
+    // scrolled, it moves up with the lines above it; it can't be cut, so once its top has
+    // gone, the render stands in for it
+    let scrolled = ui.preview_cursor;
+    let graphic = data.graphic.as_ref().filter(|(g, at)| {
+        *at >= scrolled
+            && g.width <= area.width
+            && usize::from(g.height) + at - scrolled <= usize::from(area.height)
+    });
+    if let (Some((graphic, at)), Some(render)) = (graphic, &data.render) {
+        // the terminal draws it after the frame, under the lines before it; its cells are left blank
+        let above = render.lines.iter().skip(scrolled).take(at - scrolled);
+        let above = above.cloned().collect::<Vec<_>>();
+        f.render_widget(Text::from(above), area);
+        *ui.placement.borrow_mut() = Some(Placement {
+            graphic: Arc::clone(graphic),
+            x: area.x,
+            y: area.y + (at - scrolled) as u16,
+        });
+        return;
+    }
+
     let text = match &data.command {
         PreviewCommand::InterpretFile => match data.render.as_ref() {
             Some(rendered) => rendered,
@@ -463,20 +678,132 @@ fn draw_preview(f: &mut Frame, ui: &Ui, mode: PreviewMode, area: Rect) {
 
     let allowable_cursor = text.lines.len().saturating_sub(3);
 
-    if ui.preview_cursor > 0 {
+    let skip = if mode == PreviewMode::Follow {
+        // pinned to the end, scrolling moves back up from there
+        let from_end = usize::from(area.height).saturating_add(ui.preview_cursor);
+        text.lines.len().saturating_sub(from_end)
+    } else {
+        ui.preview_cursor.min(allowable_cursor)
+    };
+
+    let matcher = ui.preview_matcher();
+    let cursor = ui.preview_focus.cursor;
+    let selected = preview_selection(ui).filter(|_| ui.preview_focus.focus);
+    // the pinned lines stay at the top, and the rest scroll under them
+    let pinned = data.pinned.min(text.lines.len());
+    let lines = text
+        .lines
+        .iter()
+        .enumerate()
+        .take(pinned)
+        .chain(text.lines.iter().enumerate().skip(pinned + skip))
+        .take(area.height.into())
+        .map(|(i, line)| {
+            let line = match &selected {
+                Some(selected) if selected.contains(&i) => {
+                    let mut line = line.clone();
+                    for span in &mut line.spans {
+                        span.style = span.style.on_dark_gray();
+                    }
+                    line
+                }
+                _ => line.clone(),
+            };
+            match &matcher {
+                Some(matcher) => highlight_matches(&line, matcher, cursor == Some(i)),
+                None => line,
+            }
+        })
+        .collect::<Vec<_>>();
+    f.render_widget(Text::from(lines), area);
+}
+
+fn draw_preview_focus(f: &mut Frame, ui: &Ui, mode: PreviewMode, area: Rect) {
+    let focus = &ui.preview_focus;
+    let status = if focus.input.value().is_empty() {
+        preview_source_line(ui, mode)
+            .map(|line| format!(" :{line}"))
+            .unwrap_or_default()
+    } else {
+        let hits = preview_hits(ui, mode);
+        let position = focus
+            .cursor
+            .and_then(|c| hits.iter().position(|&h| h == c))
+            .map(|i| (i + 1).to_string())
+            .unwrap_or_else(|| "-".to_string());
+        format!(" [{position}/{}]", hits.len())
+    };
+
+    let [input, status_area] = Layout::horizontal([
+        Constraint::Percentage(100),
+        Constraint::Length(status.len() as u16),
+    ])
+    .areas(area);
+    f.render_widget(Span::styled(status, Color::DarkGray), status_area);
+    if focus.focus {
+        draw_input_line(f, "/ ", &focus.input, input);
+    } else {
         f.render_widget(
-            Text::from(
-                text.lines
-                    .iter()
-                    .skip(ui.preview_cursor.min(allowable_cursor))
-                    .cloned()
-                    .collect::<Vec<_>>(),
-            ),
-            area,
-        )
+            Span::styled(format!("/ {}", focus.input.value()), Color::DarkGray),
+            input,
+        );
+    }
+}
+
+/// Restyle every match in the line, splitting spans where matches start and end.
+fn highlight_matches(line: &Line<'static>, matcher: &RegexMatcher, current: bool) -> Line<'static> {
+    let content = line
+        .spans
+        .iter()
+        .map(|s| s.content.as_ref())
+        .collect::<String>();
+    let mut found = Vec::new();
+    let _ = matcher.find_iter(content.as_bytes(), |m| {
+        if !m.is_empty() {
+            found.push(m.start()..m.end());
+        }
+        true
+    });
+    if found.is_empty() {
+        return line.clone();
+    }
+
+    let hit = if current {
+        Style::new().black().on_light_red()
     } else {
-        f.render_widget(text, area);
+        Style::new().black().on_yellow()
+    };
+
+    let mut spans = Vec::with_capacity(line.spans.len() + found.len() * 2);
+    let mut offset = 0;
+    for span in &line.spans {
+        let text = span.content.as_ref();
+        let end = offset + text.len();
+        let mut at = offset;
+        for m in found.iter().filter(|m| m.start < end && m.end > offset) {
+            let start = m.start.max(at);
+            let stop = m.end.min(end);
+            if start > at {
+                spans.push(Span::styled(
+                    text[at - offset..start - offset].to_string(),
+                    span.style,
+                ));
+            }
+            spans.push(Span::styled(
+                text[start - offset..stop - offset].to_string(),
+                span.style.patch(hit),
+            ));
+            at = stop;
+        }
+        if at < end {
+            spans.push(Span::styled(text[at - offset..].to_string(), span.style));
+        }
+        offset = end;
     }
+
+    let mut highlighted = line.clone();
+    highlighted.spans = spans;
+    highlighted
 }
 
 fn as_raw_preview(
@@ -524,7 +851,7 @@ fn draw_git_logs(f: &mut Frame, ui: &Ui, area: Rect) {
     let matches = git_log_matches(log_data, ui.bad_git_log.input.value(), area.height.into());
 
     // amusingly not necessarily the first (list order) item
-    let selected = matches.get(0).copied().unwrap_or_default();
+    let selected = matches.first().copied().unwrap_or_default();
 
     let lines = log_data
         .entries
@@ -0,0 +1,203 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::item::Item;
+use ignore::WalkBuilder;
+
+/// Recursive directory sizes, added up in the background by one walk of the root, like
+/// ncdu; readable while they're still being added up.
+pub struct DiskUsage {
+    totals: Arc<Mutex<HashMap<PathBuf, Arc<Total>>>>,
+    /// the walk's got to the end
+    finished: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+    /// largest size shown, for scaling the bars
+    largest: AtomicU64,
+    last_resort: Cell<Instant>,
+    settled: Cell<bool>,
+}
+
+#[derive(Default)]
+struct Total {
+    bytes: AtomicU64,
+    done: AtomicBool,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Size {
+    pub bytes: u64,
+    /// false while a directory is still being scanned
+    pub done: bool,
+}
+
+impl DiskUsage {
+    pub fn new(root: &Path) -> Self {
+        let totals = Arc::new(Mutex::new(HashMap::new()));
+        let finished = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let root = root.to_path_buf();
+        let worker_totals = Arc::clone(&totals);
+        let worker_finished = Arc::clone(&finished);
+        let worker_cancelled = Arc::clone(&cancelled);
+        thread::spawn(move || {
+            add_up(&root, &worker_totals, &worker_cancelled);
+            worker_finished.store(true, Ordering::Relaxed);
+        });
+
+        Self {
+            totals,
+            finished,
+            cancelled,
+            largest: AtomicU64::new(0),
+            last_resort: Cell::new(Instant::now()),
+            settled: Cell::new(false),
+        }
+    }
+
+    pub fn size(&self, item: &Item) -> Option<Size> {
+        let info = item.info()?;
+        let size = if info.kind.is_dir() {
+            let totals = self.totals.lock().expect("panic");
+            let total = totals.get(item.path()?)?;
+            Size {
+                bytes: total.bytes.load(Ordering::Relaxed),
+                done: total.done.load(Ordering::Relaxed),
+            }
+        } else {
+            Size {
+                bytes: info.stat().map(disk_bytes)?,
+                done: true,
+            }
+        };
+        Some(size)
+    }
+
+    /// fraction of the largest size shown so far, this one included
+    pub fn proportion(&self, size: Size) -> f32 {
+        let largest = self.largest.fetch_max(size.bytes, Ordering::Relaxed);
+        match largest.max(size.bytes) {
+            0 => 0.,
+            largest => size.bytes as f32 / largest as f32,
+        }
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        !self.finished.load(Ordering::Relaxed)
+    }
+
+    /// while totals are moving, a size sort needs redoing; but not every frame,
+    /// and once more when they've settled
+    pub fn should_resort(&self) -> bool {
+        if !self.is_scanning() {
+            return !self.settled.replace(true);
+        }
+        self.settled.set(false);
+        if self.last_resort.get().elapsed() < Duration::from_millis(500) {
+            return false;
+        }
+        self.last_resort.set(Instant::now());
+        true
+    }
+}
+
+impl Drop for DiskUsage {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Walk `root` depth first, adding everything to the total of each directory it's in;
+/// a directory's done once the walk's left it. Hardlinks are counted once, wherever
+/// they're first seen, like du.
+fn add_up(root: &Path, totals: &Mutex<HashMap<PathBuf, Arc<Total>>>, cancelled: &AtomicBool) {
+    let mut seen = HashSet::new();
+    // the directories the walk is in, outermost first
+    let mut open: Vec<(PathBuf, Arc<Total>)> = Vec::new();
+
+    let walk = WalkBuilder::new(root)
+        .standard_filters(false)
+        .follow_links(false)
+        .same_file_system(true)
+        .build();
+    for entry in walk {
+        if cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(entry) = entry else {
+            continue;
+        };
+        while open
+            .last()
+            .is_some_and(|(dir, _)| !entry.path().starts_with(dir))
+        {
+            let (_, total) = open.pop().expect("just checked");
+            total.done.store(true, Ordering::Relaxed);
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            let total = Arc::new(Total::default());
+            totals
+                .lock()
+                .expect("panic")
+                .insert(entry.path().to_path_buf(), Arc::clone(&total));
+            open.push((entry.into_path(), total));
+        }
+        if hardlink_id(&metadata).is_some_and(|inode| !seen.insert(inode)) {
+            continue;
+        }
+        let bytes = disk_bytes(&metadata);
+        for (_, total) in &open {
+            total.bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+    for (_, total) in open {
+        total.done.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(unix)]
+pub fn hardlink_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (!metadata.is_dir() && metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn hardlink_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// space actually used, rather than the apparent length
+#[cfg(unix)]
+pub fn disk_bytes(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+pub fn disk_bytes(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}{}", UNITS[0])
+    } else {
+        format!("{size:.1}{}", UNITS[unit])
+    }
+}
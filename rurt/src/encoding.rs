@@ -0,0 +1,118 @@
+use chardetng::EncodingDetector;
+use content_inspector::ContentType;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// How far in to look for the zeros which give away UTF-16 without a BOM
+const SNIFF: usize = 4096;
+
+/// Text, whatever it was written in, as UTF-8
+pub struct Decoded {
+    pub text: String,
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+/// Text in the encoding it looks to be in; `None` if it doesn't look like text at all.
+pub fn decode(content: &[u8]) -> Option<Decoded> {
+    let (encoding, bom) = match Encoding::for_bom(content) {
+        Some((encoding, _)) => (encoding, true),
+        None => (detect(content)?, false),
+    };
+    // the BOM's dropped either way
+    let (text, _) = encoding.decode_with_bom_removal(content);
+    Some(Decoded {
+        text: text.into_owned(),
+        encoding,
+        bom,
+    })
+}
+
+/// As text even if it doesn't look like it, because we were told it is.
+pub fn decode_lossy(content: &[u8]) -> Decoded {
+    decode(content).unwrap_or_else(|| Decoded {
+        text: String::from_utf8_lossy(content).into_owned(),
+        encoding: UTF_8,
+        bom: false,
+    })
+}
+
+/// Part of a file, from somewhere past any BOM, in the encoding its start was in.
+pub fn decode_as(content: &[u8], encoding: &'static Encoding) -> Decoded {
+    let (text, _) = encoding.decode_without_bom_handling(content);
+    Decoded {
+        text: text.into_owned(),
+        encoding,
+        bom: false,
+    }
+}
+
+fn detect(content: &[u8]) -> Option<&'static Encoding> {
+    if let Some(utf16) = utf16(content) {
+        return Some(utf16);
+    }
+    if content_inspector::inspect(content) == ContentType::BINARY {
+        return None;
+    }
+    match std::str::from_utf8(content) {
+        Ok(_) => return Some(UTF_8),
+        // cut off mid character, by the end of what we read
+        Err(e) if e.error_len().is_none() => return Some(UTF_8),
+        Err(_) => (),
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(content, true);
+    Some(detector.guess(None, false))
+}
+
+/// Mostly ASCII, written in UTF-16: every other byte is zero, and the ones between
+/// are readable.
+fn utf16(content: &[u8]) -> Option<&'static Encoding> {
+    let sniff = &content[..content.len().min(SNIFF)];
+    let pairs: Vec<[u8; 2]> = sniff.chunks_exact(2).map(|p| [p[0], p[1]]).collect();
+    if pairs.len() < 2 {
+        return None;
+    }
+    let readable = |b: u8| matches!(b, b'\n' | b'\r' | b'\t' | b' '..=b'~');
+    // of the pairs with a zero high byte, how many have a readable low byte
+    let ascii = |low: usize| {
+        let high = 1 - low;
+        let zero_high: Vec<_> = pairs.iter().filter(|p| p[high] == 0).collect();
+        let readable = zero_high.iter().filter(|p| readable(p[low])).count();
+        (zero_high.len(), readable)
+    };
+    for (low, encoding) in [(0, UTF_16LE), (1, UTF_16BE)] {
+        let (zero_high, readable) = ascii(low);
+        if zero_high * 10 > pairs.len() * 4 && readable * 10 >= zero_high * 9 {
+            return Some(encoding);
+        }
+    }
+    None
+}
+
+impl Decoded {
+    /// For the header: `UTF-16LE BOM, CRLF`
+    pub fn describe(&self) -> String {
+        let mut description = self.encoding.name().to_string();
+        if self.bom {
+            description.push_str(" BOM");
+        }
+        if let Some(endings) = endings(&self.text) {
+            description.push_str(", ");
+            description.push_str(endings);
+        }
+        description
+    }
+}
+
+/// `None` for a single line.
+fn endings(text: &str) -> Option<&'static str> {
+    let bytes = text.as_bytes();
+    let newlines = bytes.iter().filter(|&&b| b == b'\n').count();
+    let crlf = bytes.windows(2).filter(|w| w == b"\r\n").count();
+    match (newlines, crlf) {
+        (0, _) => None,
+        (_, 0) => Some("LF"),
+        (n, c) if n == c => Some("CRLF"),
+        _ => Some("mixed"),
+    }
+}
@@ -22,6 +22,7 @@ impl AddItem {
         self.cancelled.store(true, Ordering::Relaxed);
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn send(&self, item: Item) -> std::result::Result<(), ()> {
         if self.cancelled.load(Ordering::Relaxed) {
             return Err(());
@@ -4,7 +4,9 @@ use gix::bstr::{BString, ByteSlice};
 use gix::diff::index::ChangeRef;
 use gix::path::try_into_bstr;
 use gix::progress::Discard;
+use gix::remote::Direction;
 use gix::revision::walk::Sorting;
+use gix::state::InProgress;
 use gix::status::index_worktree::Item as IndexItem;
 use gix::status::Item as StatusItem;
 use gix::Repository;
@@ -17,6 +19,23 @@ pub struct Git {
     repo: Repository,
     status: RefCell<Cache<PathBuf, HashMap<BString, Letter>>>,
     resolved: RefCell<Cache<PathBuf, String>>,
+    summary: RefCell<Cache<PathBuf, RepoSummary>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RepoSummary {
+    pub head: Head,
+    /// (ahead, behind) relative to the upstream, if there is one
+    pub ahead_behind: Option<(usize, usize)>,
+    pub stashes: usize,
+    pub in_progress: Option<&'static str>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Head {
+    Branch(String),
+    Detached(String),
+    Unborn,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -41,13 +60,30 @@ impl Git {
             repo: repo.clone(),
             status: RefCell::new(Cache::new()),
             resolved: RefCell::new(Cache::new()),
+            summary: RefCell::new(Cache::new()),
         };
+        let summary_repo = repo.clone();
+        g.summary
+            .borrow_mut()
+            .compute(root.clone(), move || summary(&summary_repo).ok());
         g.status
             .borrow_mut()
             .compute(root, move || status(&repo).ok());
         Some(g)
     }
 
+    /// Look at the branch again; it moves, and fetches land, without the listing changing.
+    pub fn refresh_summary(&self) {
+        let repo = self.repo.clone();
+        self.summary
+            .borrow_mut()
+            .recompute(self.root.clone(), move || summary(&repo).ok());
+    }
+
+    pub fn summary(&self) -> Option<RepoSummary> {
+        self.summary.borrow_mut().get(&self.root).cloned()
+    }
+
     pub fn status(&self, abs: impl AsRef<Path>) -> Option<Letter> {
         let bstr = try_into_bstr(abs.as_ref().strip_prefix(&self.root).ok()?).ok()?;
         self.status
@@ -57,6 +93,24 @@ impl Git {
             .cloned()
     }
 
+    /// Everything changed under `dir`, by absolute path.
+    pub fn changes_under(&self, dir: impl AsRef<Path>) -> Vec<(PathBuf, Letter)> {
+        let dir = dir.as_ref();
+        let mut status = self.status.borrow_mut();
+        let Some(status) = status.get(&self.root) else {
+            return Vec::new();
+        };
+        status
+            .iter()
+            .filter_map(|(path, letter)| {
+                let path = self
+                    .root
+                    .join(gix::path::try_from_bstr(path.as_bstr()).ok()?);
+                path.starts_with(dir).then_some((path, *letter))
+            })
+            .collect()
+    }
+
     pub fn resolve(&self, path: impl AsRef<Path>) -> Option<String> {
         let path = path.as_ref();
         let repo = self.repo.clone();
@@ -98,6 +152,65 @@ fn find_file_commit(
         }))
 }
 
+fn summary(repo: &Repository) -> Result<RepoSummary> {
+    let head = repo.head()?;
+
+    let ahead_behind = (|| {
+        let local = head.id()?.detach();
+        let tracking = repo
+            .branch_remote_tracking_ref_name(head.referent_name()?, Direction::Fetch)?
+            .ok()?;
+        let upstream = repo
+            .find_reference(tracking.as_ref())
+            .ok()?
+            .into_fully_peeled_id()
+            .ok()?
+            .detach();
+        let count = |from, hidden| {
+            repo.rev_walk([from])
+                .with_hidden([hidden])
+                .all()
+                .map(|walk| walk.count())
+                .ok()
+        };
+        Some((count(local, upstream)?, count(upstream, local)?))
+    })();
+
+    let head_desc = match (head.referent_name(), head.id()) {
+        (_, None) => Head::Unborn,
+        (Some(name), _) if !head.is_detached() => Head::Branch(name.shorten().to_string()),
+        (_, Some(id)) => Head::Detached(id.shorten_or_id().to_string()),
+    };
+
+    let stashes = match repo.try_find_reference("refs/stash")? {
+        Some(stash) => stash
+            .log_iter()
+            .all()?
+            .map(|log| log.count())
+            .unwrap_or_default(),
+        None => 0,
+    };
+
+    Ok(RepoSummary {
+        head: head_desc,
+        ahead_behind,
+        stashes,
+        in_progress: repo.state().map(in_progress_marker),
+    })
+}
+
+fn in_progress_marker(state: InProgress) -> &'static str {
+    match state {
+        InProgress::ApplyMailbox => "AM",
+        InProgress::ApplyMailboxRebase => "AM/REBASE",
+        InProgress::Bisect => "BISECTING",
+        InProgress::CherryPick | InProgress::CherryPickSequence => "CHERRY-PICKING",
+        InProgress::Merge => "MERGING",
+        InProgress::Rebase | InProgress::RebaseInteractive => "REBASING",
+        InProgress::Revert | InProgress::RevertSequence => "REVERTING",
+    }
+}
+
 pub fn status(repo: &Repository) -> Result<HashMap<BString, Letter>> {
     let mut status = HashMap::with_capacity(8);
     for f in repo.status(Discard)?.into_iter([])? {
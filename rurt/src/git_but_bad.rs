@@ -20,7 +20,7 @@ pub struct LogEntry {
 }
 
 impl LogEntry {
-    pub fn as_spans(&self, matching: bool) -> Vec<Span> {
+    pub fn as_spans(&self, matching: bool) -> Vec<Span<'_>> {
         let mut spans = Vec::new();
 
         let wm = |s: Color| {
@@ -38,7 +38,7 @@ impl LogEntry {
         ]);
         if !self.decorate.is_empty() {
             spans.push(Span::styled(
-                format!("({}) ", &self.decorate),
+                format!("({}) ", self.decorate),
                 wm(Color::Yellow),
             ));
         }
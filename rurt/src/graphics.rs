@@ -0,0 +1,234 @@
+use std::env;
+use std::io::{self, Cursor, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use crossterm::cursor::{MoveTo, RestorePosition, SavePosition};
+use crossterm::queue;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use ratatui::layout::Rect;
+
+/// kitty wants the data in pieces no bigger than this
+const KITTY_CHUNK: usize = 4096;
+
+/// Rows the preview pane doesn't get: the input line, the info line, a little spare
+const NOT_PANE_ROWS: u16 = 4;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// How images get to the terminal
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Protocol {
+    /// the kitty graphics protocol, which ghostty and wezterm speak too
+    Kitty,
+    Sixel,
+    /// iTerm2's inline images
+    Iterm2,
+    /// coloured half blocks, which any terminal can show, blurrily
+    Blocks,
+}
+
+impl Protocol {
+    /// A guess from the environment, as asking the terminal would mean reading its answer
+    /// out of the input.
+    pub fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        // tmux would need everything wrapping, and gets the positions wrong anyway
+        if env::var_os("TMUX").is_some() {
+            Protocol::Blocks
+        } else if term == "xterm-kitty"
+            || env::var_os("KITTY_WINDOW_ID").is_some()
+            || ["ghostty", "WezTerm"].contains(&program.as_str())
+        {
+            Protocol::Kitty
+        } else if program == "iTerm.app" || var("LC_TERMINAL") == "iTerm2" {
+            Protocol::Iterm2
+        } else if term.contains("sixel")
+            || ["foot", "foot-extra", "mlterm", "contour"].contains(&term.as_str())
+        {
+            Protocol::Sixel
+        } else {
+            Protocol::Blocks
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Kitty => "kitty",
+            Protocol::Sixel => "sixel",
+            Protocol::Iterm2 => "iterm",
+            Protocol::Blocks => "blocks",
+        }
+    }
+}
+
+/// An image encoded for the terminal, ready to be put wherever it's going
+pub struct Graphic {
+    /// in cells
+    pub width: u16,
+    pub height: u16,
+    payload: Vec<u8>,
+}
+
+/// A graphic, and the cell its top left corner goes in
+#[derive(Clone)]
+pub struct Placement {
+    pub graphic: Arc<Graphic>,
+    pub x: u16,
+    pub y: u16,
+}
+
+impl PartialEq for Placement {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.graphic, &other.graphic) && (self.x, self.y) == (other.x, other.y)
+    }
+}
+
+/// Scale the image down to fit the cells, and encode it; `None` for blocks, which
+/// aren't a graphic. `above` is the rows of the pane which are taken before it starts.
+pub fn encode(
+    protocol: Protocol,
+    image: &DynamicImage,
+    cells: (u16, u16),
+    above: u16,
+) -> Result<Option<Graphic>> {
+    if protocol == Protocol::Blocks {
+        return Ok(None);
+    }
+    let (cell_width, cell_height, rows) = cell_size();
+    // the area is as tall as the text could scroll, but the image stays put
+    let cells = (
+        cells.0,
+        cells.1.min(rows.saturating_sub(NOT_PANE_ROWS + above)),
+    );
+    let most = (
+        u32::from(cells.0) * cell_width,
+        u32::from(cells.1) * cell_height,
+    );
+    if most.0 == 0 || most.1 == 0 {
+        return Ok(None);
+    }
+    let (width, height) = image.dimensions();
+    let image = if width > most.0 || height > most.1 {
+        image.resize(most.0, most.1, FilterType::Triangle)
+    } else {
+        image.clone()
+    };
+    let (width, height) = image.dimensions();
+
+    let payload = match protocol {
+        Protocol::Kitty => kitty(&png(&image)?),
+        Protocol::Iterm2 => {
+            let png = png(&image)?;
+            format!(
+                "\x1b]1337;File=inline=1;size={};width={width}px;height={height}px;preserveAspectRatio=1:{}\x07",
+                png.len(),
+                STANDARD.encode(&png)
+            )
+            .into_bytes()
+        }
+        Protocol::Sixel => {
+            let rgb = image.to_rgb8();
+            icy_sixel::sixel_string(
+                &rgb,
+                i32::try_from(width)?,
+                i32::try_from(height)?,
+                icy_sixel::PixelFormat::RGB888,
+                icy_sixel::DiffusionMethod::Auto,
+                icy_sixel::MethodForLargest::Auto,
+                icy_sixel::MethodForRep::Auto,
+                icy_sixel::Quality::HIGH,
+            )
+            .map_err(|e| anyhow!("sixel: {e}"))?
+            .into_bytes()
+        }
+        Protocol::Blocks => unreachable!(),
+    };
+
+    Ok(Some(Graphic {
+        width: u16::try_from(width.div_ceil(cell_width))?,
+        height: u16::try_from(height.div_ceil(cell_height))?,
+        payload,
+    }))
+}
+
+impl Graphic {
+    /// what it takes to send, which is most of what it takes to keep
+    pub fn bytes(&self) -> usize {
+        self.payload.len()
+    }
+}
+
+impl Placement {
+    /// the cells it covers
+    pub fn area(&self) -> Rect {
+        Rect::new(self.x, self.y, self.graphic.width, self.graphic.height)
+    }
+
+    /// Draw it, leaving the cursor where it was.
+    pub fn place(&self, out: &mut impl Write) -> io::Result<()> {
+        queue!(out, SavePosition, MoveTo(self.x, self.y))?;
+        out.write_all(&self.graphic.payload)?;
+        queue!(out, RestorePosition)?;
+        out.flush()
+    }
+}
+
+/// Take down whatever's been placed. kitty keeps images apart from the text, so they can
+/// just be deleted; the others are pixels in the cells, which only go when the cells are
+/// drawn again: true asks for that.
+pub fn clear(protocol: Protocol, out: &mut impl Write) -> io::Result<bool> {
+    match protocol {
+        Protocol::Kitty => {
+            out.write_all(b"\x1b_Ga=d,d=A,q=2\x1b\\")?;
+            out.flush()?;
+            Ok(false)
+        }
+        Protocol::Sixel | Protocol::Iterm2 => Ok(true),
+        Protocol::Blocks => Ok(false),
+    }
+}
+
+fn png(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// Transmit and show in one go, without moving the cursor.
+fn kitty(png: &[u8]) -> Vec<u8> {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let data = STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = Vec::with_capacity(data.len() + chunks.len() * 32);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            write!(out, "\x1b_Ga=T,f=100,i={id},q=2,C=1,m={more};")
+        } else {
+            write!(out, "\x1b_Gm={more};")
+        }
+        .expect("writing to a vec");
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    out
+}
+
+/// Pixels per cell, guessing if the terminal won't say; and the rows on screen.
+fn cell_size() -> (u32, u32, u16) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            u32::from(size.width / size.columns),
+            u32::from(size.height / size.rows),
+            size.rows,
+        ),
+        Ok(size) => (10, 20, size.rows),
+        Err(_) => (10, 20, 24),
+    }
+}
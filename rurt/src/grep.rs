@@ -0,0 +1,154 @@
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use crate::fuzz::AddItem;
+use crate::item::{convert_path, Item};
+use crate::walk::ReadOpts;
+use anyhow::Result;
+use grep_matcher::Matcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::sinks::Lossy;
+use grep_searcher::{BinaryDetection, Searcher, SearcherBuilder};
+use ignore::{WalkBuilder, WalkState};
+
+/// Lines longer than this are cut down to a window around the match
+const MAX_TEXT: usize = 256;
+
+/// Search the contents of everything under `src`, like ripgrep, listing each matching line.
+pub fn stream_grep(tx: AddItem, src: &Path, query: &str, read_opts: &ReadOpts) -> Result<()> {
+    if query.is_empty() {
+        return Ok(());
+    }
+
+    let matcher = matcher(query)?;
+
+    let ignore_files = !read_opts.show_ignored;
+    let mut walk = WalkBuilder::new(src);
+    let walk = walk
+        .follow_links(true)
+        .hidden(!read_opts.show_hidden)
+        .ignore(ignore_files)
+        .git_exclude(ignore_files)
+        .git_global(ignore_files)
+        .git_ignore(ignore_files);
+
+    // the index only knows about what a default walk would find
+    if let Some(index) = read_opts
+        .index
+        .as_ref()
+        .filter(|_| !read_opts.show_hidden && !read_opts.show_ignored)
+    {
+        if let Some(candidates) = index.candidates(src, query) {
+            let mut searcher = searcher();
+            for path in candidates {
+                if !search_file(&tx, &mut searcher, &matcher, src, &path) {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    walk.build_parallel().run(|| {
+        let tx = tx.clone();
+        let matcher = matcher.clone();
+        let mut searcher = searcher();
+        Box::new(move |entry| {
+            let Ok(entry) = entry else {
+                return WalkState::Continue;
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                return WalkState::Continue;
+            }
+            if search_file(&tx, &mut searcher, &matcher, src, entry.path()) {
+                WalkState::Continue
+            } else {
+                WalkState::Quit
+            }
+        })
+    });
+
+    Ok(())
+}
+
+fn searcher() -> Searcher {
+    SearcherBuilder::new()
+        .line_number(true)
+        .binary_detection(BinaryDetection::quit(0))
+        .build()
+}
+
+/// Send every matching line in `path` to the listing.
+/// @return false if we should stop searching
+fn search_file(
+    tx: &AddItem,
+    searcher: &mut Searcher,
+    matcher: &RegexMatcher,
+    src: &Path,
+    path: &Path,
+) -> bool {
+    if tx.cancelled.load(Ordering::Relaxed) {
+        return false;
+    }
+
+    // only stat files which turn out to match
+    let mut file = None;
+    let _ = searcher.search_path(
+        matcher,
+        path,
+        Lossy(|line, content| {
+            let Some(found) = matcher.find(content.as_bytes()).ok().flatten() else {
+                return Ok(true);
+            };
+            if file.is_none() {
+                match convert_path(src, path.to_path_buf()) {
+                    Ok(Some(Item::FileEntry { name, info })) => file = Some((name, info)),
+                    _ => return Ok(false),
+                }
+            }
+            let (name, info) = file.clone().expect("just set");
+            let (text, matched) = excerpt(content, found.start()..found.end());
+            let item = Item::ContentMatch {
+                name,
+                info,
+                line,
+                column: found.start() + 1,
+                text,
+                matched,
+            };
+            Ok(tx.send(item).is_ok())
+        }),
+    );
+
+    !tx.cancelled.load(Ordering::Relaxed)
+}
+
+/// Smart case, like ripgrep; and half-typed patterns like `foo(` are taken literally.
+pub fn matcher(query: &str) -> Result<RegexMatcher> {
+    let mut builder = RegexMatcherBuilder::new();
+    builder.case_smart(true);
+    Ok(builder
+        .build(query)
+        .or_else(|_| builder.fixed_strings(true).build(query))?)
+}
+
+fn excerpt(line: &str, matched: Range<usize>) -> (String, Range<usize>) {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let matched = matched.start.min(line.len())..matched.end.min(line.len());
+    if line.len() <= MAX_TEXT {
+        return (line.to_string(), matched);
+    }
+
+    let mut start = matched.start.saturating_sub(MAX_TEXT / 4);
+    while !line.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (start + MAX_TEXT).min(line.len());
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    let text = line[start..end].to_string();
+    let matched = matched.start - start..matched.end.clamp(matched.start, end) - start;
+    (text, matched)
+}
@@ -0,0 +1,261 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{self, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use ignore::gitignore::{gitconfig_excludes_path, Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreReason {
+    /// the ignore file containing the pattern
+    pub source: PathBuf,
+    pub pattern: String,
+}
+
+/// Why paths are ignored, worked out one after another on a thread of its own, as
+/// the listing asks
+pub struct Ignores {
+    known: RefCell<HashMap<PathBuf, Option<IgnoreReason>>>,
+    asked: RefCell<HashSet<PathBuf>>,
+    requests: Sender<(PathBuf, bool)>,
+    answers: Receiver<(PathBuf, Option<IgnoreReason>)>,
+}
+
+impl Default for Ignores {
+    fn default() -> Self {
+        let (requests, asked) = mpsc::channel::<(PathBuf, bool)>();
+        let (answer, answers) = mpsc::channel();
+        // ends when the requests do, as the `Ignores` is dropped
+        thread::spawn(move || {
+            let mut repos = HashMap::new();
+            let mut matchers = Matchers::default();
+            for (path, is_dir) in asked {
+                let dir = path.parent().map(Path::to_path_buf);
+                let repo = repos.entry(dir).or_insert_with(|| find_repo(&path)).clone();
+                let reason = why_ignored_in(&path, is_dir, repo.as_ref(), &mut matchers);
+                if answer.send((path, reason)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            known: RefCell::default(),
+            asked: RefCell::default(),
+            requests,
+            answers,
+        }
+    }
+}
+
+impl Ignores {
+    /// What's known so far; it's asked for, if it isn't.
+    pub fn reason(&self, path: impl AsRef<Path>, is_dir: bool) -> Option<IgnoreReason> {
+        self.decided(path, is_dir).flatten()
+    }
+
+    /// `None` until the worker's answered, and then the reason, if it's ignored.
+    pub fn decided(&self, path: impl AsRef<Path>, is_dir: bool) -> Option<Option<IgnoreReason>> {
+        let mut known = self.known.borrow_mut();
+        known.extend(self.answers.try_iter());
+        let path = path.as_ref();
+        if let Some(reason) = known.get(path) {
+            return Some(reason.clone());
+        }
+        if self.asked.borrow_mut().insert(path.to_path_buf()) {
+            let _ = self.requests.send((path.to_path_buf(), is_dir));
+        }
+        None
+    }
+}
+
+/// The work tree, and the `info/exclude` which goes with it
+#[derive(Clone)]
+struct Repo {
+    root: PathBuf,
+    exclude: PathBuf,
+}
+
+/// As git finds it: `.git` is a file in worktrees and submodules, and their
+/// `info/exclude` is in the repository they share.
+fn find_repo(path: &Path) -> Option<Repo> {
+    let repo = gix::discover(path.parent()?).ok()?;
+    Some(Repo {
+        root: path::absolute(repo.workdir()?).ok()?,
+        // a worktree's is reached through `..`s
+        exclude: fs::canonicalize(repo.common_dir())
+            .ok()?
+            .join("info")
+            .join("exclude"),
+    })
+}
+
+/// Ignore files, parsed once for as long as they aren't modified; by the file, and
+/// the root its patterns are relative to, as the global excludes are used in every repo
+#[derive(Default)]
+struct Matchers {
+    parsed: HashMap<(PathBuf, Option<PathBuf>), (SystemTime, Option<Gitignore>)>,
+}
+
+impl Matchers {
+    /// The rules in `file`, if there is one; patterns are relative to `root`, or to
+    /// the file's own directory.
+    fn get(&mut self, file: &Path, root: Option<&Path>) -> Option<&Gitignore> {
+        let metadata = fs::metadata(file).ok().filter(|m| m.is_file())?;
+        let modified = metadata.modified().ok()?;
+        let (parsed_at, matcher) = self
+            .parsed
+            .entry((file.to_path_buf(), root.map(Path::to_path_buf)))
+            .or_insert_with(|| (modified, parse(file, root)));
+        if *parsed_at != modified {
+            *parsed_at = modified;
+            *matcher = parse(file, root);
+        }
+        matcher.as_ref()
+    }
+}
+
+fn parse(file: &Path, root: Option<&Path>) -> Option<Gitignore> {
+    let Some(root) = root else {
+        return Some(Gitignore::new(file).0);
+    };
+    let mut builder = GitignoreBuilder::new(root);
+    if builder.add(file).is_some() {
+        return None;
+    }
+    builder.build().ok()
+}
+
+/// Find the ignore rule which excludes `path`, checking the same files as the walker,
+/// in roughly the same precedence order: `.ignore` then `.gitignore` from the deepest
+/// directory up, then `info/exclude`, then the global excludes file.
+pub fn why_ignored(path: impl AsRef<Path>, is_dir: bool) -> Option<IgnoreReason> {
+    let path = path.as_ref();
+    why_ignored_in(
+        path,
+        is_dir,
+        find_repo(path).as_ref(),
+        &mut Matchers::default(),
+    )
+}
+
+fn why_ignored_in(
+    path: &Path,
+    is_dir: bool,
+    repo: Option<&Repo>,
+    matchers: &mut Matchers,
+) -> Option<IgnoreReason> {
+    // to line up with the repository's root, which is
+    let path = &path::absolute(path).ok()?;
+    let repo_root = repo.map(|repo| repo.root.as_path());
+
+    for dir in path.ancestors().skip(1) {
+        let mut names = vec![".ignore"];
+        if repo_root.is_some() {
+            names.push(".gitignore");
+        }
+        for name in names {
+            let Some(matcher) = matchers.get(&dir.join(name), None) else {
+                continue;
+            };
+            if let Some(decided) = decide(matcher, path, is_dir) {
+                return decided;
+            }
+        }
+        if Some(dir) == repo_root {
+            break;
+        }
+    }
+
+    let repo = repo?;
+    let excludes = [Some(repo.exclude.clone()), gitconfig_excludes_path()];
+    for exclude in excludes.into_iter().flatten() {
+        let Some(matcher) = matchers.get(&exclude, Some(&repo.root)) else {
+            continue;
+        };
+        if let Some(decided) = decide(matcher, path, is_dir) {
+            return decided;
+        }
+    }
+
+    None
+}
+
+/// `None` if this matcher has no opinion, `Some(None)` if it whitelists the path
+fn decide(matcher: &Gitignore, path: &Path, is_dir: bool) -> Option<Option<IgnoreReason>> {
+    match matcher.matched_path_or_any_parents(path, is_dir) {
+        Match::None => None,
+        Match::Whitelist(_) => Some(None),
+        Match::Ignore(glob) => Some(Some(IgnoreReason {
+            source: glob
+                .from()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_else(|| matcher.path().to_path_buf()),
+            pattern: glob.original().to_string(),
+        })),
+    }
+}
+
+/// The closest existing `.gitignore` between `path` and the repository root,
+/// or where one would be created at the root.
+pub fn nearest_gitignore(path: impl AsRef<Path>) -> Result<PathBuf> {
+    let path = path.as_ref();
+    let path = &path::absolute(path)?;
+    let parent = path
+        .parent()
+        .ok_or_else(|| anyhow!("{path:?} has no parent"))?;
+    let repo_root = find_repo(path).map(|repo| repo.root);
+    for dir in path.ancestors().skip(1) {
+        let candidate = dir.join(".gitignore");
+        if candidate.is_file() {
+            return Ok(candidate);
+        }
+        if Some(dir) == repo_root.as_deref() {
+            return Ok(candidate);
+        }
+    }
+    Ok(parent.join(".gitignore"))
+}
+
+/// Append a pattern for `path` (or for its extension) to the nearest `.gitignore`.
+/// Returns the file written and the pattern added.
+pub fn append_to_gitignore(
+    path: impl AsRef<Path>,
+    is_dir: bool,
+    extension_only: bool,
+) -> Result<(PathBuf, String)> {
+    let path = &path::absolute(path.as_ref())?;
+    let gitignore = nearest_gitignore(path)?;
+
+    let pattern = if extension_only {
+        let ext = path
+            .extension()
+            .ok_or_else(|| anyhow!("{path:?} has no extension"))?;
+        format!("*.{}", ext.to_string_lossy())
+    } else {
+        let base = gitignore.parent().expect("file in a directory");
+        let rel = path.strip_prefix(base)?;
+        let mut pattern = format!("/{}", rel.display());
+        if is_dir {
+            pattern.push('/');
+        }
+        pattern
+    };
+
+    let existing = fs::read(&gitignore).unwrap_or_default();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&gitignore)?;
+    if !existing.is_empty() && !existing.ends_with(b"\n") {
+        file.write_all(b"\n")?;
+    }
+    writeln!(file, "{pattern}")?;
+
+    Ok((gitignore, pattern))
+}
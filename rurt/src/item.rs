@@ -1,4 +1,5 @@
 use crate::colour::Colour;
+use crate::du::{human_size, Size};
 use crate::git::Letter;
 use crate::walk::DResult;
 use anyhow::{anyhow, Context, Result};
@@ -11,24 +12,93 @@ use std::borrow::Cow;
 use std::ffi::OsString;
 use std::fs;
 use std::fs::FileType;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Item {
-    FileEntry { name: OsString, info: Arc<ItemInfo> },
-    WalkError { msg: String },
+    FileEntry {
+        name: OsString,
+        info: Arc<ItemInfo>,
+    },
+    /// a line in a file which matched a content search
+    ContentMatch {
+        name: OsString,
+        info: Arc<ItemInfo>,
+        line: u64,
+        /// 1-based, in bytes, like ripgrep's --column
+        column: usize,
+        /// the line, or a window of it around the match
+        text: String,
+        /// where the match is in `text`
+        matched: Range<usize>,
+    },
+    WalkError {
+        msg: String,
+    },
 }
 
 #[derive(Clone, Debug)]
 pub struct ItemInfo {
-    pub file_type: FileType,
+    pub kind: FileKind,
+    file_type: Option<FileType>,
     path: PathBuf,
     filename: OsString,
     metadata: Option<fs::Metadata>,
     pub link_dest: Option<PathBuf>,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+impl FileKind {
+    pub fn is_file(&self) -> bool {
+        *self == FileKind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        *self == FileKind::Dir
+    }
+}
+
+impl From<FileType> for FileKind {
+    fn from(file_type: FileType) -> Self {
+        if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_file() {
+            FileKind::File
+        } else if file_type.is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::Other
+        }
+    }
+}
+
+impl ItemInfo {
+    pub fn stat(&self) -> Option<&fs::Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// for listings which know the kind without touching the filesystem, e.g. the git index
+    pub fn unstatted(path: PathBuf, kind: FileKind) -> Self {
+        Self {
+            kind,
+            file_type: None,
+            filename: path.file_name().unwrap_or_default().to_os_string(),
+            metadata: None,
+            link_dest: None,
+            path,
+        }
+    }
+}
+
 impl PartialEq for ItemInfo {
     fn eq(&self, other: &Self) -> bool {
         self.path.eq(&other.path)
@@ -47,7 +117,7 @@ impl Colorable for ItemInfo {
     }
 
     fn file_type(&self) -> Option<FileType> {
-        Some(self.file_type)
+        self.file_type
     }
 
     fn metadata(&self) -> Option<std::fs::Metadata> {
@@ -66,39 +136,74 @@ pub struct ItemView<'a> {
 }
 
 impl Item {
-    pub fn text(&self) -> Cow<str> {
+    pub fn text(&self) -> Cow<'_, str> {
         match self {
             Item::FileEntry { name, .. } => name.to_string_lossy(),
+            Item::ContentMatch {
+                name, line, text, ..
+            } => format!("{}:{line}: {text}", name.to_string_lossy()).into(),
             Item::WalkError { msg } => msg.into(),
         }
     }
 
-    pub fn path(&self) -> Option<&Path> {
+    pub fn info(&self) -> Option<&ItemInfo> {
         match self {
-            Item::FileEntry { info, .. } => Some(&info.path),
+            Item::FileEntry { info, .. } | Item::ContentMatch { info, .. } => Some(info),
             Item::WalkError { .. } => None,
         }
     }
 
+    pub fn path(&self) -> Option<&Path> {
+        self.info().map(|info| info.path.as_path())
+    }
+
+    /// (line, column) of a content match
+    pub fn position(&self) -> Option<(u64, usize)> {
+        match self {
+            Item::ContentMatch { line, column, .. } => Some((*line, *column)),
+            _ => None,
+        }
+    }
+
     // rot: 0: fresh, 1: stale
-    pub fn render(&self, context: &ViewContext) -> ItemView {
+    pub fn render(&self, context: &ViewContext) -> ItemView<'_> {
         match self {
-            Item::WalkError { msg } => {
-                return ItemView {
-                    primary: vec![Span::styled(
-                        format!("error walking: {msg}"),
-                        context.styling.error,
-                    )],
-                    ..Default::default()
-                };
+            Item::WalkError { msg } => ItemView {
+                primary: vec![Span::styled(
+                    format!("error walking: {msg}"),
+                    context.styling.error,
+                )],
+                ..Default::default()
+            },
+            Item::FileEntry { name, info, .. } => render_file_entry(name, info, context),
+            Item::ContentMatch {
+                name,
+                info,
+                line,
+                text,
+                matched,
+                ..
+            } => {
+                let mut view = render_file_entry(name, info, context);
+                let hit = [
+                    Span::styled(format!(":{line}: "), context.styling.line_number),
+                    Span::raw(&text[..matched.start]),
+                    Span::styled(&text[matched.clone()], context.styling.matched),
+                    Span::raw(&text[matched.end..]),
+                ];
+                view.primary.extend(hit.clone());
+                view.short.extend(hit);
+                view
             }
-            Item::FileEntry { name, info, .. } => return render_file_entry(name, info, context),
-        };
+        }
     }
 }
 
 pub struct ViewContext<'a> {
     pub git_status: Option<Letter>,
+    pub ignored: bool,
+    /// disk usage, and its fraction of the largest seen
+    pub size: Option<(Size, f32)>,
     pub git_info: Option<String>,
     pub rot: f32,
     pub styling: &'a Styling,
@@ -174,18 +279,49 @@ fn render_file_entry<'a>(
         }
     }
 
+    let hidden = info.filename.as_encoded_bytes().starts_with(b".");
+    for span in view.primary.iter_mut().chain(view.short.iter_mut()) {
+        if hidden {
+            span.style = span.style.patch(context.styling.hidden);
+        }
+        if context.ignored {
+            span.style = span.style.patch(context.styling.ignored);
+        }
+    }
+
     view.annotation = if let Some(git_status) = context.git_status {
         vec![Span::styled(
             format!("[{git_status:?}]"),
             context.styling.git_info,
         )]
+    } else if context.ignored {
+        vec![Span::styled("[!!]", context.styling.git_info)]
     } else {
         vec![Span::raw("    ")]
     };
 
+    if let Some((size, proportion)) = context.size {
+        const BAR_WIDTH: usize = 10;
+        let filled = ((proportion * BAR_WIDTH as f32).round() as usize).min(BAR_WIDTH);
+        // still adding up
+        let style = if size.done {
+            context.styling.size
+        } else {
+            context.styling.size.dim()
+        };
+        view.annotation.splice(
+            0..0,
+            [
+                Span::styled(format!("{:>7} ", human_size(size.bytes)), style),
+                Span::styled("\u{2588}".repeat(filled), style), // █
+                Span::raw(" ".repeat(BAR_WIDTH - filled + 1)),
+            ],
+        );
+    }
+
     if let Some(git_info) = &context.git_info {
         view.extra = Some(vec![Span::styled(
-            format!("{git_info}"),
+            git_info.to_string(),
             context.styling.git_info,
         )])
     }
@@ -202,6 +338,16 @@ impl PartialOrd for Item {
 impl Ord for Item {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match (self, other) {
+            (
+                Item::ContentMatch {
+                    name: an, line: al, ..
+                },
+                Item::ContentMatch {
+                    name: bn, line: bl, ..
+                },
+            ) => an.cmp(bn).then(al.cmp(bl)),
+            (Item::ContentMatch { .. }, _) => std::cmp::Ordering::Less,
+            (_, Item::ContentMatch { .. }) => std::cmp::Ordering::Greater,
             (
                 Item::FileEntry {
                     name: an, info: at, ..
@@ -210,8 +356,8 @@ impl Ord for Item {
                     name: bn, info: bt, ..
                 },
             ) => {
-                let a = at.file_type.is_dir();
-                let b = bt.file_type.is_dir();
+                let a = at.kind.is_dir();
+                let b = bt.kind.is_dir();
                 if a != b {
                     b.cmp(&a)
                 } else {
@@ -275,8 +421,38 @@ fn convert_resolution_entry(root: impl AsRef<Path>, f: DirEntry) -> Result<Optio
             path,
             filename: f.file_name().to_os_string(),
             metadata: f.metadata().ok(),
-            file_type,
+            kind: file_type.into(),
+            file_type: Some(file_type),
+            link_dest,
+        }),
+    }))
+}
+
+/// Build an entry for a single path outside of a walk, e.g. from a filesystem event.
+pub fn convert_path(root: impl AsRef<Path>, path: PathBuf) -> Result<Option<Item>> {
+    let name = path.strip_prefix(root)?.as_os_str().to_owned();
+    if name.is_empty() {
+        return Ok(None);
+    }
+
+    let link_metadata = path.symlink_metadata()?;
+    let link_dest = if link_metadata.is_symlink() {
+        fs::canonicalize(&path).ok()
+    } else {
+        None
+    };
+    // like the walker, follow links, but keep the link itself if it's broken
+    let metadata = fs::metadata(&path).unwrap_or(link_metadata);
+
+    Ok(Some(Item::FileEntry {
+        name,
+        info: Arc::new(ItemInfo {
+            filename: path.file_name().unwrap_or_default().to_os_string(),
+            kind: metadata.file_type().into(),
+            file_type: Some(metadata.file_type()),
             link_dest,
+            metadata: Some(metadata),
+            path,
         }),
     }))
 }
@@ -300,7 +476,8 @@ fn convert_resolution_path(root: impl AsRef<Path>, path: PathBuf) -> Result<Opti
         name,
         info: Arc::new(ItemInfo {
             filename,
-            file_type: metadata.file_type(),
+            kind: metadata.file_type().into(),
+            file_type: Some(metadata.file_type()),
             link_dest: fs::read_link(&path).ok(),
             metadata: Some(metadata),
             path,
@@ -315,6 +492,11 @@ pub struct Styling {
     pub error: Style,
     pub symlink: Style,
     pub git_info: Style,
+    pub hidden: Style,
+    pub ignored: Style,
+    pub size: Style,
+    pub line_number: Style,
+    pub matched: Style,
 }
 
 impl Styling {
@@ -330,6 +512,11 @@ impl Styling {
             symlink: RStyle::new().light_magenta(),
             error: RStyle::default().light_red(),
             git_info: RStyle::default().fg(Color::DarkGray),
+            hidden: RStyle::default().italic(),
+            ignored: RStyle::default().dim().crossed_out(),
+            size: RStyle::default().fg(Color::Indexed(109)),
+            line_number: RStyle::default().light_green(),
+            matched: RStyle::default().light_red().bold(),
         }
     }
 
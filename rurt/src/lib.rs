@@ -1,8 +1,10 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::action::Action;
 use crate::dir_stack::DirStack;
 use crate::git::Git;
+use crate::previewers::Previewers;
 use crate::walk::ReadOpts;
 use crossterm::event::{KeyCode, KeyModifiers};
 use draw::ViewOpts;
@@ -11,22 +13,44 @@ use nucleo::{Config, Matcher};
 
 pub mod action;
 mod alt_screen;
+mod archive;
 mod cache;
+pub mod cleanup;
 mod colour;
+mod data_tree;
+mod dir_preview;
 pub mod dir_stack;
 pub mod draw;
+mod du;
+mod encoding;
 pub mod fuzz;
 mod git;
 mod git_but_bad;
+pub mod graphics;
+mod grep;
+mod ignores;
 pub mod item;
 mod line_stop;
+mod markdown;
+mod media;
+mod object_file;
+mod paging;
+pub mod path_index;
+mod persist;
+mod photo;
+mod pool;
 mod preview;
+pub mod previewers;
 pub mod ratui;
 mod snapped;
 pub mod store;
+mod table;
+pub mod text_index;
+mod tracked;
 pub mod tui_log;
 mod ui_state;
 pub mod walk;
+mod watch;
 
 #[derive(Copy, Clone)]
 pub struct ResultOpts {
@@ -42,6 +66,9 @@ pub struct App {
     pub view_opts: ViewOpts,
     pub result_opts: ResultOpts,
     pub bindings: Vec<Binding>,
+    pub previewers: Arc<Previewers>,
+    /// run with `{}` as the file to play; without one, the file's default application
+    pub player: Option<String>,
 }
 
 impl App {
@@ -12,6 +12,11 @@ impl LineStopFmtWrite {
             inner: String::with_capacity(32 * remaining_new_lines),
         }
     }
+
+    /// It stopped taking any more, so there may have been more to come.
+    pub fn is_full(&self) -> bool {
+        self.remaining_new_lines == 0
+    }
 }
 
 impl fmt::Write for LineStopFmtWrite {
@@ -40,6 +45,11 @@ impl LineStopIoWrite {
             inner: Vec::with_capacity(32 * remaining_new_lines),
         }
     }
+
+    /// It stopped taking any more, so there may have been more to come.
+    pub fn is_full(&self) -> bool {
+        self.remaining_new_lines == 0
+    }
 }
 
 impl io::Write for LineStopIoWrite {
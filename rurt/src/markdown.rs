@@ -0,0 +1,419 @@
+use std::path::Path;
+
+use ansi_to_tui::IntoText as _;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::prelude::*;
+
+use crate::ui_state::URect;
+
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["md", "markdown", "mdown", "mkd"]
+                .iter()
+                .any(|md| ext.eq_ignore_ascii_case(md))
+        })
+}
+
+/// Markdown as it'd look rendered, give or take; wrapped to the area, and
+/// stopping once it's filled, like the other previews.
+pub fn render(content: &[u8], area: URect, coloured: bool) -> Text<'static> {
+    let content = String::from_utf8_lossy(content);
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+
+    let mut r = Renderer {
+        width: area.width,
+        coloured,
+        ..Default::default()
+    };
+    for event in Parser::new_ext(&content, options) {
+        r.event(event);
+        if r.lines.len() > area.height {
+            break;
+        }
+    }
+    r.flush();
+    r.lines.truncate(area.height);
+
+    if !coloured {
+        // keep the bold and italics, which aren't colours
+        for span in r.lines.iter_mut().flat_map(|line| line.spans.iter_mut()) {
+            span.style = Style::new().add_modifier(span.style.add_modifier);
+        }
+    }
+    Text::from(r.lines)
+}
+
+#[derive(Default)]
+struct Renderer {
+    width: usize,
+    coloured: bool,
+    lines: Vec<Line<'static>>,
+    /// the inline content of the block being built, not yet wrapped
+    spans: Vec<Span<'static>>,
+    styles: Vec<Style>,
+    /// what goes before every line: quote bars, and indents for list items
+    prefixes: Vec<Span<'static>>,
+    /// the next number for ordered lists, for each list we're in
+    lists: Vec<Option<u64>>,
+    /// the bullet for a list item, which replaces its indent on its first line
+    marker: Option<Span<'static>>,
+    /// the language and text of a fenced block
+    code: Option<(String, String)>,
+    table: Option<Table>,
+    /// a blank line is owed before the next block
+    blank: bool,
+}
+
+#[derive(Default)]
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<Vec<Span<'static>>>>,
+    /// the header is the first row
+    header: bool,
+}
+
+impl Renderer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code {
+                Some((_, code)) => code.push_str(&text),
+                None => self.push(text.into_string(), Style::new()),
+            },
+            Event::Code(code) => self.push(code.into_string(), Style::new().yellow()),
+            Event::InlineMath(math) | Event::DisplayMath(math) => {
+                self.push(math.into_string(), Style::new().yellow())
+            }
+            Event::Html(html) | Event::InlineHtml(html) => {
+                self.push(html.trim_end().to_string(), Style::new().dark_gray())
+            }
+            Event::FootnoteReference(name) => {
+                self.push(format!("[^{name}]"), Style::new().light_blue())
+            }
+            Event::SoftBreak => self.push(" ".to_string(), Style::new()),
+            Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.start_block();
+                let width = self.width.saturating_sub(self.prefix_width());
+                self.push_line(vec![Span::styled(
+                    "─".repeat(width),
+                    Style::new().dark_gray(),
+                )]);
+                self.blank = true;
+            }
+            Event::TaskListMarker(done) => self.push(
+                if done { "[x] " } else { "[ ] " }.to_string(),
+                Style::new().light_green(),
+            ),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::HtmlBlock => self.start_block(),
+            Tag::Heading { level, .. } => {
+                self.start_block();
+                self.styles.push(match level {
+                    HeadingLevel::H1 => Style::new().light_magenta().bold().underlined(),
+                    HeadingLevel::H2 => Style::new().light_magenta().bold(),
+                    _ => Style::new().magenta().bold(),
+                });
+            }
+            Tag::BlockQuote(_) => {
+                self.start_block();
+                self.prefixes
+                    .push(Span::styled("│ ", Style::new().dark_gray()));
+            }
+            Tag::CodeBlock(kind) => {
+                self.start_block();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some((language, String::new()));
+            }
+            Tag::List(start) => {
+                self.start_block();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let depth = self.lists.len();
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => ["• ", "◦ ", "▪ "][depth.saturating_sub(1) % 3].to_string(),
+                };
+                self.prefixes
+                    .push(Span::raw(" ".repeat(bullet.chars().count())));
+                self.marker = Some(Span::styled(bullet, Style::new().light_blue()));
+            }
+            Tag::FootnoteDefinition(name) => {
+                self.start_block();
+                self.push(format!("[^{name}]: "), Style::new().light_blue());
+            }
+            Tag::Table(alignments) => {
+                self.start_block();
+                self.table = Some(Table {
+                    alignments,
+                    ..Default::default()
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => {
+                if let Some(row) = self.table.as_mut().and_then(|t| t.rows.last_mut()) {
+                    row.push(Vec::new());
+                }
+            }
+            Tag::Emphasis => self.styles.push(Style::new().italic()),
+            Tag::Strong => self.styles.push(Style::new().bold()),
+            Tag::Strikethrough => self.styles.push(Style::new().crossed_out()),
+            Tag::Link { .. } => self.styles.push(Style::new().light_blue().underlined()),
+            Tag::Image { .. } => {
+                self.push("![".to_string(), Style::new().dark_gray());
+                self.styles.push(Style::new().light_blue());
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::HtmlBlock | TagEnd::FootnoteDefinition => {
+                self.flush();
+                self.blank = true;
+            }
+            TagEnd::Heading(_) => {
+                self.flush();
+                self.styles.pop();
+                self.blank = true;
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.prefixes.pop();
+                self.blank = true;
+            }
+            TagEnd::CodeBlock => {
+                if let Some((language, code)) = self.code.take() {
+                    self.code_block(&language, &code);
+                }
+                self.blank = true;
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+                // a list inside an item runs straight on to the next item
+                self.blank = self.lists.is_empty();
+            }
+            TagEnd::Item => {
+                self.flush();
+                self.prefixes.pop();
+                self.marker = None;
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.table(table);
+                }
+                self.blank = true;
+            }
+            TagEnd::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.header = true;
+                }
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link => {
+                self.styles.pop();
+            }
+            TagEnd::Image => {
+                self.styles.pop();
+                self.push("]".to_string(), Style::new().dark_gray());
+            }
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, text: String, style: Style) {
+        let style = self
+            .styles
+            .iter()
+            .fold(Style::new(), |acc, s| acc.patch(*s))
+            .patch(style);
+        let span = Span::styled(text, style);
+        match self.table.as_mut().and_then(|t| t.rows.last_mut()) {
+            Some(row) => {
+                if let Some(cell) = row.last_mut() {
+                    cell.push(span);
+                }
+            }
+            None => self.spans.push(span),
+        }
+    }
+
+    fn start_block(&mut self) {
+        self.flush();
+        if self.blank && !self.lines.is_empty() {
+            let prefix = self.prefix(false);
+            self.lines.push(Line::from(prefix));
+        }
+        self.blank = false;
+    }
+
+    /// Wrap up the inline content so far into lines.
+    fn flush(&mut self) {
+        if self.spans.is_empty() {
+            return;
+        }
+        let spans = std::mem::take(&mut self.spans);
+        let width = self.width.saturating_sub(self.prefix_width()).max(1);
+        for line in wrap(spans, width) {
+            self.push_line(line);
+        }
+    }
+
+    fn prefix_width(&self) -> usize {
+        self.prefixes.iter().map(Span::width).sum()
+    }
+
+    /// The prefixes, with the item's bullet in place of its indent, if it's its first line.
+    fn prefix(&mut self, first: bool) -> Vec<Span<'static>> {
+        let mut prefix = self.prefixes.clone();
+        if first {
+            if let (Some(marker), Some(last)) = (self.marker.take(), prefix.last_mut()) {
+                *last = marker;
+            }
+        }
+        prefix
+    }
+
+    fn push_line(&mut self, spans: Vec<Span<'static>>) {
+        let mut line = self.prefix(true);
+        line.extend(spans);
+        self.lines.push(Line::from(line));
+    }
+
+    fn code_block(&mut self, language: &str, code: &str) {
+        let width = self.width.saturating_sub(self.prefix_width());
+        let mut out = String::new();
+        let mut printer = bat::PrettyPrinter::new();
+        printer
+            .input(bat::Input::from_bytes(code.as_bytes()))
+            .header(false)
+            .colored_output(self.coloured)
+            .term_width(width)
+            .tab_width(Some(2))
+            .line_numbers(false)
+            .use_italics(false);
+        if !language.is_empty() {
+            printer.language(language);
+        }
+        let highlighted = printer
+            .print_with_writer(Some(&mut out))
+            .ok()
+            .and_then(|_| out.into_text().ok());
+        match highlighted {
+            Some(text) => {
+                for line in text.lines {
+                    self.push_line(line.spans);
+                }
+            }
+            // probably a language bat doesn't know
+            None => {
+                for line in code.lines() {
+                    self.push_line(vec![Span::raw(line.to_string())]);
+                }
+            }
+        }
+    }
+
+    fn table(&mut self, table: Table) {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|c| {
+                table
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.get(c))
+                    .map(|cell| cell.iter().map(Span::width).sum())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let border = Style::new().dark_gray();
+
+        for (r, row) in table.rows.into_iter().enumerate() {
+            let header = table.header && r == 0;
+            let mut line = Vec::new();
+            for (c, width) in widths.iter().enumerate() {
+                if c > 0 {
+                    line.push(Span::styled(" │ ", border));
+                }
+                let cell = row.get(c).cloned().unwrap_or_default();
+                let pad = width - cell.iter().map(Span::width).sum::<usize>();
+                let (before, after) = match table.alignments.get(c) {
+                    Some(Alignment::Right) => (pad, 0),
+                    Some(Alignment::Center) => (pad / 2, pad - pad / 2),
+                    _ => (0, pad),
+                };
+                line.push(Span::raw(" ".repeat(before)));
+                line.extend(
+                    cell.into_iter()
+                        .map(|span| if header { span.bold() } else { span }),
+                );
+                line.push(Span::raw(" ".repeat(after)));
+            }
+            self.push_line(line);
+            if header {
+                let rule = widths
+                    .iter()
+                    .map(|w| "─".repeat(*w))
+                    .collect::<Vec<_>>()
+                    .join("─┼─");
+                self.push_line(vec![Span::styled(rule, border)]);
+            }
+        }
+    }
+}
+
+/// Break at spaces to fit the width; words which are too long on their own stick out.
+fn wrap(spans: Vec<Span<'static>>, width: usize) -> Vec<Vec<Span<'static>>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<Span<'static>> = Vec::new();
+    let mut used = 0;
+    for span in spans {
+        for word in span.content.split_inclusive(' ') {
+            let fits = Span::raw(word.trim_end()).width();
+            if used > 0 && used + fits > width {
+                lines.push(std::mem::take(&mut line));
+                used = 0;
+            }
+            if used == 0 && word.trim().is_empty() {
+                continue;
+            }
+            used += Span::raw(word).width();
+            match line.last_mut() {
+                Some(last) if last.style == span.style => last.content.to_mut().push_str(word),
+                _ => line.push(Span::styled(word.to_string(), span.style)),
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use file_type::FileType;
+use ratatui::prelude::*;
+use serde::Deserialize;
+
+use crate::du::human_size;
+use crate::preview::preview_header;
+
+// not .ts, which is far more often typescript than a transport stream
+const MEDIA_EXTENSIONS: [&str; 21] = [
+    "mp3", "flac", "ogg", "oga", "opus", "wav", "aac", "m4a", "wma", "aiff", "ape", "mka", "mp4",
+    "m4v", "mov", "mkv", "webm", "avi", "wmv", "mpg", "mpeg",
+];
+
+/// Tags worth showing, in the order they're shown
+const TAGS: [&str; 7] = [
+    "title",
+    "artist",
+    "album_artist",
+    "album",
+    "track",
+    "date",
+    "genre",
+];
+
+/// What `ffprobe -print_format json -show_format -show_streams` says, or the parts we want
+#[derive(Deserialize)]
+struct Probe {
+    format: Format,
+    #[serde(default)]
+    streams: Vec<Stream>,
+}
+
+#[derive(Deserialize)]
+struct Format {
+    format_long_name: Option<String>,
+    // ffprobe gives the numbers as strings
+    duration: Option<String>,
+    bit_rate: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Stream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    profile: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<u32>,
+    channel_layout: Option<String>,
+    bit_rate: Option<String>,
+    #[serde(default)]
+    disposition: HashMap<String, u8>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+pub fn is_media(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| MEDIA_EXTENSIONS.iter().any(|m| ext.eq_ignore_ascii_case(m)))
+}
+
+/// The container, its tags, and a line for each stream, from ffprobe's json.
+pub fn render(probe: &[u8], showing: &Path) -> Result<Text<'static>> {
+    let probe: Probe = serde_json::from_slice(probe)?;
+    let format = probe.format;
+    // vorbis comments come in capitals, and id3 in whatever
+    let tags: HashMap<String, String> = format
+        .tags
+        .into_iter()
+        .map(|(k, v)| (k.to_ascii_lowercase(), v))
+        .collect();
+
+    let mut fields = Vec::new();
+    fields.extend(format.format_long_name.map(|f| ("format", f)));
+    let duration = format.duration.as_deref().and_then(number);
+    fields.extend(duration.map(|d| ("duration", clock(d))));
+    fields.extend(
+        format
+            .bit_rate
+            .as_deref()
+            .and_then(bit_rate)
+            .map(|b| ("bitrate", b)),
+    );
+    for tag in TAGS {
+        if let Some(value) = tags.get(tag).filter(|v| !v.trim().is_empty()) {
+            fields.push((tag, value.trim().to_string()));
+        }
+    }
+
+    let mut lines = vec![preview_header("media", showing)];
+    lines.extend(fields.into_iter().map(|(key, value)| field(key, value)));
+    if !probe.streams.is_empty() {
+        lines.push(Line::default());
+    }
+    for stream in probe.streams {
+        let (kind, description) = describe(&stream);
+        lines.push(field(&kind, description));
+    }
+    Ok(Text::from(lines))
+}
+
+/// Without ffprobe, what the start of the file says it is, or failing that its name,
+/// and how big it is; None if neither says.
+pub fn render_detected(head: &[u8], size: u64, showing: &Path) -> Option<Text<'static>> {
+    let by_content = FileType::from_bytes(head);
+    let (detected, guessed) = if by_content.extensions().is_empty() {
+        let ext = showing.extension()?.to_str()?.to_ascii_lowercase();
+        (*FileType::from_extension(ext).first()?, true)
+    } else {
+        (by_content, false)
+    };
+    let format = if guessed {
+        format!("{}, going by the name", detected.name())
+    } else {
+        detected.name().to_string()
+    };
+    let mut lines = vec![preview_header("file", showing), field("format", format)];
+    lines.extend(
+        detected
+            .media_types()
+            .first()
+            .map(|mime| field("type", mime.to_string())),
+    );
+    lines.push(field("size", human_size(size)));
+    lines.push(Line::default());
+    lines.push(Line::styled(
+        "install ffprobe for the streams and tags",
+        Style::new().dark_gray(),
+    ));
+    Some(Text::from(lines))
+}
+
+fn field(key: &str, value: String) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{key:>12} "), Style::new().dark_gray()),
+        Span::raw(value),
+    ])
+}
+
+/// What sort of stream, and the details which matter for that sort.
+fn describe(stream: &Stream) -> (String, String) {
+    let mut parts = Vec::new();
+    parts.extend(stream.codec_name.as_ref().map(|codec| {
+        match stream.profile.as_ref().filter(|p| *p != "unknown") {
+            Some(profile) => format!("{codec} ({profile})"),
+            None => codec.clone(),
+        }
+    }));
+
+    let kind = stream.codec_type.as_deref().unwrap_or("stream");
+    match kind {
+        // album art comes as a one frame video
+        "video" if stream.disposition.get("attached_pic") == Some(&1) => {
+            parts.extend(dimensions(stream));
+            return ("cover".to_string(), parts.join(", "));
+        }
+        "video" => {
+            parts.extend(dimensions(stream));
+            parts.extend(
+                stream
+                    .avg_frame_rate
+                    .as_deref()
+                    .and_then(frame_rate)
+                    .map(|fps| format!("{fps:.2} fps")),
+            );
+        }
+        "audio" => {
+            parts.extend(
+                stream
+                    .sample_rate
+                    .as_deref()
+                    .and_then(number)
+                    .map(|hz| format!("{} kHz", hz / 1000.0)),
+            );
+            parts.extend(
+                stream
+                    .channel_layout
+                    .clone()
+                    .or_else(|| stream.channels.map(|c| format!("{c} channels"))),
+            );
+        }
+        _ => (),
+    }
+    parts.extend(stream.bit_rate.as_deref().and_then(bit_rate));
+    parts.extend(
+        ["language", "title"]
+            .iter()
+            .filter_map(|t| stream.tags.get(*t))
+            .filter(|v| !v.is_empty() && *v != "und")
+            .cloned(),
+    );
+    (kind.to_string(), parts.join(", "))
+}
+
+fn dimensions(stream: &Stream) -> Option<String> {
+    Some(format!("{} × {}", stream.width?, stream.height?))
+}
+
+fn number(value: &str) -> Option<f64> {
+    value.parse().ok()
+}
+
+/// `30000/1001`, or `0/0` when nobody knows
+fn frame_rate(value: &str) -> Option<f64> {
+    let (num, den) = value.split_once('/')?;
+    let (num, den) = (number(num)?, number(den)?);
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+fn bit_rate(value: &str) -> Option<String> {
+    let bits = number(value)?;
+    Some(if bits >= 1e6 {
+        format!("{:.1} Mb/s", bits / 1e6)
+    } else {
+        format!("{:.0} kb/s", bits / 1e3)
+    })
+}
+
+/// `1:02:03`, or `2:03` if it's under an hour
+fn clock(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
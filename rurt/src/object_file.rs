@@ -0,0 +1,234 @@
+use std::fs::File as FsFile;
+use std::path::Path;
+
+use anyhow::Result;
+use object::elf::DT_NEEDED;
+use object::read::archive::ArchiveFile;
+use object::read::elf::{Dyn, ElfFile, FileHeader};
+use object::{Endianness, File, FileKind, Object, ObjectSection, ObjectSymbol, ReadCache, ReadRef};
+use ratatui::prelude::*;
+
+use crate::du::human_size;
+use crate::preview::preview_header;
+
+/// Symbols listed, before the rest are only counted
+const SYMBOL_LIMIT: usize = 10_000;
+
+/// Executables, libraries and objects which can be taken apart, from the start of the file.
+pub fn is_object(head: &[u8]) -> bool {
+    matches!(
+        FileKind::parse(head),
+        Ok(FileKind::Elf32
+            | FileKind::Elf64
+            | FileKind::Archive
+            | FileKind::MachO32
+            | FileKind::MachO64
+            | FileKind::Pe32
+            | FileKind::Pe64)
+    )
+}
+
+/// What the file is, what it needs, what's in it, and what it offers; reading only the
+/// parts it needs, so huge debug builds are fine.
+pub fn render(path: &Path) -> Result<Text<'static>> {
+    let cache = ReadCache::new(FsFile::open(path)?);
+    let mut lines = vec![preview_header("object", path)];
+    if FileKind::parse(&cache)? == FileKind::Archive {
+        archive(&cache, &mut lines)?;
+    } else {
+        object(&File::parse(&cache)?, &mut lines);
+    }
+    Ok(Text::from(lines))
+}
+
+fn object<'d, R: ReadRef<'d>>(file: &File<'d, R>, lines: &mut Vec<Line<'static>>) {
+    let format = format!(
+        "{:?} {}-bit, {} endian",
+        file.format(),
+        if file.is_64() { 64 } else { 32 },
+        if file.is_little_endian() {
+            "little"
+        } else {
+            "big"
+        },
+    );
+    lines.push(field("format", format));
+    lines.push(field("arch", format!("{:?}", file.architecture())));
+    lines.push(field("kind", format!("{:?}", file.kind())));
+    if file.entry() != 0 {
+        lines.push(field("entry", format!("{:#x}", file.entry())));
+    }
+    if let Ok(Some(id)) = file.build_id() {
+        lines.push(field("build id", hex(id)));
+    }
+    if file.has_debug_symbols() {
+        lines.push(field("debug", "has debug info".to_string()));
+    }
+
+    let libraries = libraries(file);
+    heading(lines, "libraries", libraries.len());
+    lines.extend(libraries.into_iter().map(|l| Line::raw(format!("  {l}"))));
+
+    let sections: Vec<_> = file
+        .sections()
+        .filter_map(|s| Some((s.name().ok()?.to_string(), s.size())))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+    heading(lines, "sections", sections.len());
+    let widest = sections.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+    let dim = Style::new().dark_gray();
+    lines.extend(sections.into_iter().map(|(name, size)| {
+        Line::from(vec![
+            Span::raw(format!("  {name:widest$}  ")),
+            Span::styled(format!("{:>8}", human_size(size)), dim),
+        ])
+    }));
+
+    let symbols = exported(file);
+    heading(lines, "symbols", symbols.len());
+    symbol_lines(symbols, lines);
+}
+
+/// Each member, and the symbols it defines.
+fn archive<'d, R: ReadRef<'d>>(data: R, lines: &mut Vec<Line<'static>>) -> Result<()> {
+    let archive = ArchiveFile::parse(data)?;
+    let members: Vec<_> = archive.members().filter_map(|m| m.ok()).collect();
+    lines.push(field("format", format!("{:?} archive", archive.kind())));
+    heading(lines, "members", members.len());
+    let mut total = 0;
+    for member in members {
+        let name = String::from_utf8_lossy(member.name()).into_owned();
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {name}"), Style::new().bold()),
+            Span::styled(
+                format!("  {}", human_size(member.size())),
+                Style::new().dark_gray(),
+            ),
+        ]));
+        let symbols = match member.data(data).map(File::parse) {
+            Ok(Ok(file)) => defined(&file),
+            _ => continue,
+        };
+        let room = SYMBOL_LIMIT.saturating_sub(total);
+        total += symbols.len();
+        symbol_lines(symbols.into_iter().take(room).collect(), lines);
+    }
+    if total > SYMBOL_LIMIT {
+        lines.push(Line::styled(
+            format!("  … {} more symbols", total - SYMBOL_LIMIT),
+            Style::new().dark_gray(),
+        ));
+    }
+    Ok(())
+}
+
+fn symbol_lines(mut symbols: Vec<String>, lines: &mut Vec<Line<'static>>) {
+    symbols.sort_unstable();
+    let more = symbols.len().saturating_sub(SYMBOL_LIMIT);
+    lines.extend(
+        symbols
+            .into_iter()
+            .take(SYMBOL_LIMIT)
+            .map(|s| Line::raw(format!("    {s}"))),
+    );
+    if more > 0 {
+        lines.push(Line::styled(
+            format!("    … {more} more"),
+            Style::new().dark_gray(),
+        ));
+    }
+}
+
+/// The shared libraries it's linked against.
+fn libraries<'d, R: ReadRef<'d>>(file: &File<'d, R>) -> Vec<String> {
+    let mut libraries = match file {
+        File::Elf32(elf) => needed(elf),
+        File::Elf64(elf) => needed(elf),
+        // the others say which library each import comes from
+        _ => file
+            .imports()
+            .unwrap_or_default()
+            .iter()
+            .map(|i| String::from_utf8_lossy(i.library()).into_owned())
+            .filter(|l| !l.is_empty())
+            .collect(),
+    };
+    if !matches!(file, File::Elf32(_) | File::Elf64(_)) {
+        libraries.sort_unstable();
+        libraries.dedup();
+    }
+    libraries
+}
+
+/// `DT_NEEDED`, in the order the loader looks for them.
+fn needed<'d, Elf, R>(elf: &ElfFile<'d, Elf, R>) -> Vec<String>
+where
+    Elf: FileHeader<Endian = Endianness>,
+    R: ReadRef<'d>,
+{
+    let endian = elf.endian();
+    let table = elf.elf_section_table();
+    let Ok(Some((entries, link))) = table.dynamic(endian, elf.data()) else {
+        return Vec::new();
+    };
+    let Ok(strings) = table.strings(endian, elf.data(), link) else {
+        return Vec::new();
+    };
+    entries
+        .iter()
+        .filter(|d| d.tag32(endian) == Some(DT_NEEDED))
+        .filter_map(|d| strings.get(d.val32(endian)?).ok())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect()
+}
+
+/// What it offers to be linked against: the dynamic exports of a library, or for
+/// anything without any, the global symbols it defines.
+fn exported<'d, R: ReadRef<'d>>(file: &File<'d, R>) -> Vec<String> {
+    let exports: Vec<String> = file
+        .exports()
+        .unwrap_or_default()
+        .iter()
+        .map(|e| demangle(e.name()))
+        .collect();
+    if exports.is_empty() {
+        defined(file)
+    } else {
+        exports
+    }
+}
+
+fn defined<'d, R: ReadRef<'d>>(file: &File<'d, R>) -> Vec<String> {
+    file.symbols()
+        .filter(|s| s.is_global() && s.is_definition())
+        .filter_map(|s| s.name_bytes().ok().map(demangle))
+        .collect()
+}
+
+fn demangle(name: &[u8]) -> String {
+    let name = String::from_utf8_lossy(name);
+    match rustc_demangle::try_demangle(&name) {
+        // without the hash
+        Ok(demangled) => format!("{demangled:#}"),
+        Err(_) => name.into_owned(),
+    }
+}
+
+fn heading(lines: &mut Vec<Line<'static>>, name: &str, count: usize) {
+    lines.push(Line::default());
+    lines.push(Line::from(vec![
+        Span::styled(name.to_string(), Style::new().bold()),
+        Span::styled(format!(" ({count})"), Style::new().dark_gray()),
+    ]));
+}
+
+fn field(key: &str, value: String) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{key:>9} "), Style::new().dark_gray()),
+        Span::raw(value),
+    ])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use encoding_rs::Encoding;
+
+use crate::preview::{stamp, Stamp};
+
+/// How much of a big file a page of its preview reads
+pub const PAGE_BYTES: u64 = 256 * 1024;
+
+/// Lines between the offsets an index keeps
+const STRIDE: usize = 1024;
+
+/// Read at a time while indexing
+const CHUNK: usize = 1024 * 1024;
+
+/// Where the preview of a file too big to read whole starts
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Page {
+    /// a line of text, from 1
+    Line(usize),
+    /// a byte, for anything else
+    Byte(u64),
+    /// some way through, until the file's been looked at; 100 is the end
+    Percent(u8),
+}
+
+/// Where to show a big file from, if not its start, and what's needed to get there
+#[derive(Copy, Clone)]
+pub struct Paging<'i> {
+    pub page: Option<Page>,
+    /// on screen, so the end can fill them
+    pub rows: usize,
+    pub indexes: &'i LineIndexes,
+}
+
+/// How a big file gets paged through
+#[derive(Copy, Clone)]
+pub enum Kind {
+    /// by line, which needs newlines to look like newlines
+    Text(&'static Encoding),
+    Bytes,
+}
+
+/// What a page of a big file covers, to move on from
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Window {
+    /// a `Line` or a `Byte`, with any percentage worked out
+    pub first: Page,
+    /// where the next page starts, once all of this one's rendered; `None` at the end
+    pub next: Option<Page>,
+    /// lines, or bytes, per line of the render, to page back by
+    pub step: u64,
+}
+
+impl Window {
+    /// The page `lines` lines of the render back, and how far down it this one starts.
+    pub fn back(&self, lines: usize) -> Option<(Page, usize)> {
+        match self.first {
+            Page::Line(first) if first > 1 => {
+                let to = first.saturating_sub(lines).max(1);
+                Some((Page::Line(to), first - to))
+            }
+            Page::Byte(first) if first > 0 => {
+                let to = first.saturating_sub(lines as u64 * self.step);
+                Some((Page::Byte(to), ((first - to) / self.step) as usize))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Line indexes for the big files which have been paged through, so paging around
+/// only reads what it hasn't already
+#[derive(Default)]
+pub struct LineIndexes {
+    indexes: Mutex<HashMap<PathBuf, Arc<Mutex<LineIndex>>>>,
+}
+
+impl LineIndexes {
+    /// The file's index, started again if the file's changed since.
+    pub fn get(&self, path: &Path) -> Arc<Mutex<LineIndex>> {
+        let stamp = stamp(path);
+        let mut indexes = self.indexes.lock().expect("panic");
+        let index = indexes.entry(path.to_path_buf()).or_default();
+        let mut locked = index.lock().expect("panic");
+        if locked.stamp != stamp {
+            *locked = LineIndex {
+                stamp,
+                ..LineIndex::default()
+            };
+        }
+        drop(locked);
+        Arc::clone(index)
+    }
+}
+
+/// Where every `STRIDE`th line starts, found as far into the file as anyone's needed
+pub struct LineIndex {
+    stamp: Option<Stamp>,
+    /// `starts[i]` is the offset of line `i * STRIDE + 1`
+    starts: Vec<u64>,
+    /// how far it's read, the newlines on the way, and where the line after the last starts
+    scanned: u64,
+    newlines: usize,
+    last_start: u64,
+    /// read to the end
+    complete: bool,
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        Self {
+            stamp: None,
+            starts: vec![0],
+            scanned: 0,
+            newlines: 0,
+            last_start: 0,
+            complete: false,
+        }
+    }
+}
+
+impl LineIndex {
+    /// Read on until there's `enough`, or the end.
+    fn scan(
+        &mut self,
+        file: &mut File,
+        cancelled: &AtomicBool,
+        enough: impl Fn(&Self) -> bool,
+    ) -> Result<()> {
+        file.seek(SeekFrom::Start(self.scanned))?;
+        let mut buf = vec![0; CHUNK];
+        while !self.complete && !enough(self) {
+            if cancelled.load(Ordering::Relaxed) {
+                bail!("cancelled");
+            }
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                self.complete = true;
+                break;
+            }
+            for newline in memchr::memchr_iter(b'\n', &buf[..read]) {
+                self.newlines += 1;
+                self.last_start = self.scanned + newline as u64 + 1;
+                if self.newlines.is_multiple_of(STRIDE) {
+                    self.starts.push(self.last_start);
+                }
+            }
+            self.scanned += read as u64;
+        }
+        Ok(())
+    }
+
+    /// Where the line starts, or the last line, if there aren't that many; and which
+    /// line that was.
+    pub fn start(
+        &mut self,
+        file: &mut File,
+        line: usize,
+        cancelled: &AtomicBool,
+    ) -> Result<(usize, u64)> {
+        let line = line.max(1);
+        let stride = (line - 1) / STRIDE;
+        self.scan(file, cancelled, |index| index.starts.len() > stride)?;
+        let stride = stride.min(self.starts.len() - 1);
+        let (mut at, mut offset) = (stride * STRIDE + 1, self.starts[stride]);
+
+        let len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0; CHUNK];
+        let mut pos = offset;
+        while at < line {
+            if cancelled.load(Ordering::Relaxed) {
+                bail!("cancelled");
+            }
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            for newline in memchr::memchr_iter(b'\n', &buf[..read]) {
+                let next = pos + newline as u64 + 1;
+                // the "line" after a trailing newline isn't one
+                if at == line || next >= len {
+                    break;
+                }
+                at += 1;
+                offset = next;
+            }
+            pos += read as u64;
+        }
+        Ok((at, offset))
+    }
+
+    /// The line the byte's on.
+    pub fn line_at(
+        &mut self,
+        file: &mut File,
+        offset: u64,
+        cancelled: &AtomicBool,
+    ) -> Result<usize> {
+        self.scan(file, cancelled, |index| index.scanned > offset)?;
+        let stride = self.starts.partition_point(|&start| start <= offset) - 1;
+        let from = self.starts[stride];
+
+        file.seek(SeekFrom::Start(from))?;
+        let mut counted = 0;
+        let mut remaining = offset - from;
+        let mut buf = vec![0; CHUNK];
+        while remaining > 0 {
+            if cancelled.load(Ordering::Relaxed) {
+                bail!("cancelled");
+            }
+            let want = buf.len().min(remaining as usize);
+            let read = file.read(&mut buf[..want])?;
+            if read == 0 {
+                break;
+            }
+            counted += memchr::memchr_iter(b'\n', &buf[..read]).count();
+            remaining -= read as u64;
+        }
+        Ok(stride * STRIDE + 1 + counted)
+    }
+
+    /// How many lines there are, reading to the end if nobody has yet.
+    pub fn lines(&mut self, file: &mut File, cancelled: &AtomicBool) -> Result<usize> {
+        self.scan(file, cancelled, |_| false)?;
+        // a last line with no newline still counts
+        Ok(self.newlines + usize::from(self.scanned > self.last_start))
+    }
+}
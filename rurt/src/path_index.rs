@@ -0,0 +1,190 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::fuzz::AddItem;
+use crate::item::{FileKind, Item, ItemInfo};
+use crate::persist::{index_file, read_path, read_u32, take, write_atomically, write_path};
+use crate::tracked::Emitter;
+use crate::walk::{mode_accepts, walk_builder, ReadOpts};
+use anyhow::{bail, Result};
+use log::info;
+
+const MAGIC: &[u8] = b"rurtpth1";
+
+/// relative to the root, sorted
+type Paths = Vec<(PathBuf, FileKind)>;
+
+const REFRESH_EVERY: Duration = Duration::from_secs(300);
+
+/// On-disk lists of every path under some roots, as a default walk would find them,
+/// re-walked in the background. Like `locate`, listings come out of here instantly,
+/// but can be a few minutes behind the disk.
+pub struct PathIndex {
+    roots: Vec<Arc<RootPaths>>,
+}
+
+struct RootPaths {
+    root: PathBuf,
+    /// None until it's been loaded or walked
+    paths: RwLock<Option<Arc<Paths>>>,
+}
+
+impl PathIndex {
+    pub fn start(roots: Vec<PathBuf>) -> Arc<Self> {
+        let roots = roots
+            .into_iter()
+            .map(|root| {
+                let root = Arc::new(RootPaths {
+                    root,
+                    paths: RwLock::new(None),
+                });
+                let maintained = Arc::clone(&root);
+                thread::spawn(move || maintained.maintain());
+                root
+            })
+            .collect();
+        Arc::new(Self { roots })
+    }
+
+    /// List everything under `src` recursively, if an index covers it.
+    /// @return false if there's no index to answer from, so it needs walking
+    pub fn stream(&self, tx: &AddItem, src: &Path, read_opts: &ReadOpts) -> bool {
+        let Some(root) = self.roots.iter().find(|r| src.starts_with(&r.root)) else {
+            return false;
+        };
+        let Some(paths) = root.paths() else {
+            return false;
+        };
+        let Ok(prefix) = src.strip_prefix(&root.root) else {
+            return false;
+        };
+
+        let mut emitter = Emitter::new(tx, &root.root, prefix, read_opts);
+        for (path, kind) in paths.iter() {
+            if emitter.emit(path, *kind) {
+                break;
+            }
+        }
+        true
+    }
+
+    /// List everything in every index, by absolute path.
+    pub fn stream_global(&self, tx: &AddItem, read_opts: &ReadOpts) {
+        for root in &self.roots {
+            let Some(paths) = root.paths() else {
+                info!("path index: {:?} isn't ready yet", root.root);
+                continue;
+            };
+            for (path, kind) in paths.iter() {
+                let path = root.root.join(path);
+                let item = Item::FileEntry {
+                    name: path.clone().into_os_string(),
+                    info: Arc::new(ItemInfo::unstatted(path, *kind)),
+                };
+                if mode_accepts(read_opts, &item) && tx.send(item).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl RootPaths {
+    fn paths(&self) -> Option<Arc<Paths>> {
+        self.paths.read().ok()?.clone()
+    }
+
+    fn maintain(&self) {
+        let store = index_file(&self.root, "paths");
+        if let Some(store) = &store {
+            match load(store) {
+                Ok(loaded) => {
+                    info!("path index: loaded {} for {:?}", loaded.len(), self.root);
+                    *self.paths.write().expect("panic") = Some(Arc::new(loaded));
+                }
+                Err(e) => info!("path index: not loading {store:?}: {e}"),
+            }
+        }
+
+        loop {
+            let paths = walk(&self.root);
+            let changed = self.paths().is_none_or(|old| *old != paths);
+            if changed {
+                info!("path index: {} paths under {:?}", paths.len(), self.root);
+                if let Some(store) = &store {
+                    if let Err(e) = save(store, &paths) {
+                        info!("path index: couldn't save {store:?}: {e}");
+                    }
+                }
+                *self.paths.write().expect("panic") = Some(Arc::new(paths));
+            }
+            thread::sleep(REFRESH_EVERY);
+        }
+    }
+}
+
+fn walk(root: &Path) -> Paths {
+    // as a recursive listing with the default options finds them, links followed
+    let mut paths = walk_builder(root, &ReadOpts::default())
+        .build()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path().strip_prefix(root).ok()?;
+            if path.as_os_str().is_empty() {
+                return None;
+            }
+            let kind = entry.file_type().map_or(FileKind::Other, FileKind::from);
+            Some((path.to_path_buf(), kind))
+        })
+        .collect::<Vec<_>>();
+    paths.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    paths
+}
+
+fn kind_byte(kind: FileKind) -> u8 {
+    match kind {
+        FileKind::File => 0,
+        FileKind::Dir => 1,
+        FileKind::Symlink => 2,
+        FileKind::Other => 3,
+    }
+}
+
+fn byte_kind(byte: u8) -> FileKind {
+    match byte {
+        0 => FileKind::File,
+        1 => FileKind::Dir,
+        2 => FileKind::Symlink,
+        _ => FileKind::Other,
+    }
+}
+
+fn save(store: &Path, paths: &Paths) -> Result<()> {
+    write_atomically(store, |out| {
+        out.write_all(MAGIC)?;
+        out.write_all(&(paths.len() as u32).to_le_bytes())?;
+        for (path, kind) in paths {
+            out.write_all(&[kind_byte(*kind)])?;
+            write_path(out, path)?;
+        }
+        Ok(())
+    })
+}
+
+fn load(store: &Path) -> Result<Paths> {
+    let data = fs::read(store)?;
+    let mut buf = data.as_slice();
+    if take(&mut buf, MAGIC.len())? != MAGIC {
+        bail!("not a path index, or an old one");
+    }
+    (0..read_u32(&mut buf)?)
+        .map(|_| {
+            let kind = byte_kind(take(&mut buf, 1)?[0]);
+            Ok((read_path(&mut buf)?, kind))
+        })
+        .collect()
+}
@@ -0,0 +1,86 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+/// Where the `kind` index for `root` lives; the name only needs to be stable enough
+/// that we usually find it again.
+pub fn index_file(root: &Path, kind: &str) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    root.hash(&mut hasher);
+    Some(
+        dirs::cache_dir()?
+            .join("rurt")
+            .join(format!("{:016x}.{kind}", hasher.finish())),
+    )
+}
+
+/// Write to a temporary file, then move it over `store`, so readers never see half of it.
+pub fn write_atomically(
+    store: &Path,
+    f: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    let dir = store.parent().ok_or_else(|| anyhow!("no cache dir"))?;
+    fs::create_dir_all(dir)?;
+    let tmp = store.with_extension("tmp");
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    f(&mut out)?;
+    out.flush()?;
+    out.into_inner()?.sync_all()?;
+    fs::rename(tmp, store)?;
+    Ok(())
+}
+
+pub fn take<'b>(buf: &mut &'b [u8], len: usize) -> Result<&'b [u8]> {
+    if buf.len() < len {
+        bail!("truncated index");
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+pub fn read_u32(buf: &mut &[u8]) -> Result<u32> {
+    Ok(u32::from_le_bytes(take(buf, 4)?.try_into()?))
+}
+
+pub fn read_u64(buf: &mut &[u8]) -> Result<u64> {
+    Ok(u64::from_le_bytes(take(buf, 8)?.try_into()?))
+}
+
+pub fn write_path(out: &mut impl Write, path: &Path) -> Result<()> {
+    let bytes = path_bytes(path);
+    out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    out.write_all(&bytes)?;
+    Ok(())
+}
+
+pub fn read_path(buf: &mut &[u8]) -> Result<PathBuf> {
+    let len = read_u32(buf)? as usize;
+    Ok(path_from_bytes(take(buf, len)?))
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
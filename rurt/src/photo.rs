@@ -0,0 +1,236 @@
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use exif::{Exif, Field, In, Tag, Value};
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use ratatui::prelude::*;
+
+/// Formats which carry EXIF; anything else isn't worth opening for a date
+const EXIF_EXTENSIONS: [&str; 12] = [
+    "jpg", "jpeg", "jpe", "tif", "tiff", "png", "webp", "heic", "heif", "avif", "dng", "nef",
+];
+
+/// Capture dates, as they're wanted for sorting, read in the background once per path
+#[derive(Default)]
+pub struct CaptureDates {
+    dates: Arc<Mutex<HashMap<PathBuf, Option<u64>>>>,
+    asked: RefCell<HashSet<PathBuf>>,
+    /// started on the first photo asked about
+    queue: OnceCell<Sender<PathBuf>>,
+    cancelled: Arc<AtomicBool>,
+    /// dates have come in since the sort last looked
+    arrived: Arc<AtomicBool>,
+    last_resort: Cell<Option<Instant>>,
+}
+
+impl CaptureDates {
+    /// When the photo was taken, as digits which sort as a date: `20240601140359`.
+    /// Until it's been read, it's as undated as a photo without one.
+    pub fn taken(&self, path: &Path) -> Option<u64> {
+        if let Some(date) = self.dates.lock().expect("panic").get(path) {
+            return *date;
+        }
+        if has_exif(path) && self.asked.borrow_mut().insert(path.to_path_buf()) {
+            let _ = self.queue().send(path.to_path_buf());
+        }
+        None
+    }
+
+    fn queue(&self) -> &Sender<PathBuf> {
+        self.queue.get_or_init(|| {
+            let (queue, rx) = channel::<PathBuf>();
+            let dates = Arc::clone(&self.dates);
+            let cancelled = Arc::clone(&self.cancelled);
+            let arrived = Arc::clone(&self.arrived);
+            thread::spawn(move || {
+                for path in rx {
+                    if cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let date = read_exif(&path, &[]).and_then(|exif| taken(&exif));
+                    dates.lock().expect("panic").insert(path, date);
+                    arrived.store(true, Ordering::Relaxed);
+                }
+            });
+            queue
+        })
+    }
+
+    /// as dates come in, a sort by them needs redoing; but not every frame
+    pub fn should_resort(&self) -> bool {
+        if self
+            .last_resort
+            .get()
+            .is_some_and(|last| last.elapsed() < Duration::from_millis(500))
+        {
+            return false;
+        }
+        if !self.arrived.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+        self.last_resort.set(Some(Instant::now()));
+        true
+    }
+}
+
+impl Drop for CaptureDates {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn has_exif(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXIF_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// From the file if there is one, else from what we have of it, e.g. inside an archive.
+pub fn read_exif(showing: &Path, content: &[u8]) -> Option<Exif> {
+    let reader = exif::Reader::new();
+    if showing.is_file() {
+        let mut file = BufReader::new(File::open(showing).ok()?);
+        reader.read_from_container(&mut file).ok()
+    } else {
+        reader.read_from_container(&mut Cursor::new(content)).ok()
+    }
+}
+
+/// What the image is, and where and how it was taken, a line each.
+pub fn details(image: &DynamicImage, content: &[u8], exif: Option<&Exif>) -> Vec<Line<'static>> {
+    let (width, height) = image.dimensions();
+    let mut fields = vec![
+        (
+            "size",
+            format!(
+                "{width} × {height}, {:.1} MP",
+                f64::from(width) * f64::from(height) / 1e6
+            ),
+        ),
+        ("colour", format!("{:?}", image.color())),
+    ];
+    if let Ok(format) = image::guess_format(content) {
+        fields.push(("format", format_name(format)));
+    }
+
+    if let Some(exif) = exif {
+        let make = ascii(exif, Tag::Make);
+        let model = ascii(exif, Tag::Model);
+        let camera = match (make, model) {
+            // the model usually says the make too: "Canon" "Canon EOS R5"
+            (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+            (Some(make), Some(model)) => Some(format!("{make} {model}")),
+            (make, model) => make.or(model),
+        };
+        fields.extend(camera.map(|c| ("camera", c)));
+        fields.extend(ascii(exif, Tag::LensModel).map(|l| ("lens", l)));
+
+        let exposure: Vec<String> = [
+            Tag::ExposureTime,
+            Tag::FNumber,
+            Tag::PhotographicSensitivity,
+            Tag::FocalLength,
+        ]
+        .into_iter()
+        .filter_map(|tag| {
+            let field = exif.get_field(tag, In::PRIMARY)?;
+            let value = field.display_value().with_unit(exif).to_string();
+            Some(if tag == Tag::PhotographicSensitivity {
+                format!("ISO {value}")
+            } else {
+                value
+            })
+        })
+        .collect();
+        if !exposure.is_empty() {
+            fields.push(("exposure", exposure.join("  ")));
+        }
+
+        if let (Some(lat), Some(lon)) = (
+            degrees(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+            degrees(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+        ) {
+            fields.push(("gps", format!("{lat:.5}, {lon:.5}")));
+        }
+
+        let taken = exif
+            .get_field(Tag::DateTimeOriginal, In::PRIMARY)
+            .or_else(|| exif.get_field(Tag::DateTime, In::PRIMARY));
+        fields.extend(taken.map(|f| ("taken", f.display_value().to_string())));
+    }
+
+    let dim = Style::new().dark_gray();
+    fields
+        .into_iter()
+        .map(|(key, value)| {
+            Line::from(vec![
+                Span::styled(format!("{key:>9} "), dim),
+                Span::raw(value),
+            ])
+        })
+        .collect()
+}
+
+fn format_name(format: ImageFormat) -> String {
+    format
+        .extensions_str()
+        .first()
+        .map_or_else(|| format!("{format:?}"), |ext| ext.to_ascii_uppercase())
+}
+
+fn taken(exif: &Exif) -> Option<u64> {
+    [Tag::DateTimeOriginal, Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| ascii(exif, tag))
+        .and_then(|date| date_digits(&date))
+}
+
+/// `2024:06:01 14:03:59` as `20240601140359`; cameras without a clock write zeros,
+/// which is no date at all
+fn date_digits(date: &str) -> Option<u64> {
+    let digits: String = date.chars().filter(char::is_ascii_digit).take(14).collect();
+    if digits.len() < 14 {
+        return None;
+    }
+    digits.parse().ok().filter(|&date| date > 0)
+}
+
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => {
+            let value = String::from_utf8_lossy(values.first()?).trim().to_string();
+            (!value.is_empty()).then_some(value)
+        }
+        _ => None,
+    }
+}
+
+/// Degrees, minutes and seconds as signed decimal degrees; `negative` is the reference
+/// which means south or west.
+fn degrees(exif: &Exif, tag: Tag, reference: Tag, negative: &str) -> Option<f64> {
+    let Field {
+        value: Value::Rational(parts),
+        ..
+    } = exif.get_field(tag, In::PRIMARY)?
+    else {
+        return None;
+    };
+    let degrees = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, scale)| part.to_f64() / scale)
+        .sum::<f64>();
+    Some(if ascii(exif, reference).as_deref() == Some(negative) {
+        -degrees
+    } else {
+        degrees
+    })
+}
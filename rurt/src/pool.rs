@@ -0,0 +1,83 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use log::info;
+
+struct Job {
+    cancelled: Arc<AtomicBool>,
+    run: Box<dyn FnOnce() + Send>,
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: Mutex<VecDeque<Job>>,
+    ready: Condvar,
+}
+
+/// A few threads which run the newest job first; by the time an old job
+/// comes up, it's probably been cancelled, and is skipped. A job which panics
+/// only loses itself, not its thread.
+pub struct Pool {
+    size: usize,
+    queue: Arc<Queue>,
+    started: bool,
+}
+
+impl Pool {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            queue: Arc::default(),
+            started: false,
+        }
+    }
+
+    pub fn submit(&mut self, cancelled: Arc<AtomicBool>, run: impl FnOnce() + Send + 'static) {
+        if !self.started {
+            self.started = true;
+            for _ in 0..self.size {
+                let queue = Arc::clone(&self.queue);
+                thread::spawn(move || work(&queue));
+            }
+        }
+
+        let mut jobs = self.queue.jobs.lock().expect("panic");
+        jobs.retain(|job| !job.cancelled.load(Ordering::Relaxed));
+        jobs.push_back(Job {
+            cancelled,
+            run: Box::new(run),
+        });
+        self.queue.ready.notify_one();
+    }
+}
+
+fn work(queue: &Queue) {
+    loop {
+        let job = {
+            let mut jobs = queue.jobs.lock().expect("panic");
+            loop {
+                match jobs.pop_back() {
+                    Some(job) if job.cancelled.load(Ordering::Relaxed) => continue,
+                    Some(job) => break job,
+                    None => jobs = queue.ready.wait(jobs).expect("panic"),
+                }
+            }
+        };
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(job.run)) {
+            info!("pool: job panicked: {}", panic_message(&*panic));
+        }
+    }
+}
+
+/// What a panic said, if it said it in a string.
+pub fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown")
+}
@@ -1,36 +1,94 @@
+use crate::archive::{self, Member};
+use crate::data_tree::{self, Tree};
+use crate::dir_preview::{self, Listing};
 use crate::draw::PreviewMode;
+use crate::du::human_size;
+use crate::encoding::{self, Decoded};
+use crate::graphics::{self, Graphic, Protocol};
+use crate::ignores::why_ignored;
 use crate::line_stop::{LineStopFmtWrite, LineStopIoWrite};
+use crate::markdown;
+use crate::media;
+use crate::object_file;
+use crate::paging::{Kind, LineIndexes, Page, Paging, Window, PAGE_BYTES};
+use crate::photo;
+use crate::pool::Pool;
+use crate::previewers::{shell_command, Builtin, Previewers, Renderer, Rule};
+use crate::table;
 use crate::ui_state::URect;
 use ansi_to_tui::IntoText;
-use anyhow::{anyhow, Result};
-use content_inspector::ContentType;
+use anyhow::{anyhow, bail, Result};
 use image::{DynamicImage, GenericImageView};
+use log::info;
 use ratatui::prelude::*;
 use std::collections::VecDeque;
 use std::ffi::OsStr;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
-use std::thread::JoinHandle;
-use std::time::{Duration, Instant};
-use std::{fs, io};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::{fs, io, mem};
 
-#[derive(Default)]
+/// Roughly how much memory previews may hold on to, before the least recently used go
+const CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+const WORKERS: usize = 3;
+
+/// ffprobe is quick, unless the file is on something slow
+const MEDIA_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How much of a file we read to preview it
+const STREAM_LIMIT: usize = 1024 * 1024;
+
+/// Least recently used first
 pub struct Previews {
     pub inner: VecDeque<Preview>,
+    pool: Pool,
+    pub previewers: Arc<Previewers>,
+    pub line_indexes: Arc<LineIndexes>,
 }
 
 pub struct Preview {
     pub showing: PathBuf,
     pub mode: PreviewMode,
     pub target_area: URect,
-    pub coloured: bool,
+    pub style: PreviewStyle,
+    /// the file's (mtime, size) when the preview started; a change means it's stale
+    pub stamp: Option<Stamp>,
+    /// a line to open at and highlight, for content matches
+    pub line: Option<usize>,
+    /// somewhere in a big file to show, rather than its start
+    pub page: Option<Page>,
     pub data: Arc<Mutex<PreviewedData>>,
-    pub worker: JoinHandle<()>,
+    pub finished: Arc<AtomicBool>,
+    /// set when the preview is dropped, so the work stops
+    pub cancelled: Arc<AtomicBool>,
     pub started: Instant,
 }
 
+/// How a preview looks, beyond what's in it
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct PreviewStyle {
+    pub coloured: bool,
+    /// show markdown and the like rendered, rather than as source
+    pub rendered: bool,
+    /// as the listing does, for directories
+    pub hidden: bool,
+    pub ignored: bool,
+    pub graphics: Protocol,
+}
+
+pub type Stamp = (SystemTime, u64);
+
+pub fn stamp(path: impl AsRef<Path>) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
 #[derive(Default)]
 pub enum PreviewCommand {
     #[default]
@@ -44,79 +102,566 @@ pub struct PreviewedData {
     pub command: PreviewCommand,
     pub content: Vec<u8>,
     pub render: Option<Text<'static>>,
+    /// for the tree preview, which renders it again as it's folded
+    pub tree: Option<Tree>,
+    /// lines at the top of the render which stay put while it scrolls
+    pub pinned: usize,
+    /// the image, for a terminal which can show it properly, and the line of the render
+    /// it goes on; the render is the fallback
+    pub graphic: Option<(Arc<Graphic>, usize)>,
+    /// what part of a big file the render covers, when it's being paged through
+    pub window: Option<Window>,
+    /// the text behind bat's line numbers, decoded and with the `\r`s dropped as it's
+    /// shown; None if the render hasn't got them down the side
+    pub source: Option<String>,
+}
+
+/// Everything a preview is made with, besides what it's of and where it goes
+#[derive(Copy, Clone)]
+pub struct PreviewRequest<'r> {
+    pub style: PreviewStyle,
+    pub area: URect,
+    /// a line to open at and highlight, for content matches
+    pub line: Option<usize>,
+    pub paging: Paging<'r>,
+    pub previewers: &'r Previewers,
+    /// how to show a directory
+    pub listing: &'r Listing,
+    pub cancelled: &'r AtomicBool,
 }
 
 pub fn run_preview(
     pathref: impl AsRef<Path>,
-    coloured: bool,
     mode: PreviewMode,
     preview: Arc<Mutex<PreviewedData>>,
-    area: URect,
+    request: &PreviewRequest,
 ) -> Result<()> {
+    let PreviewRequest {
+        style,
+        area,
+        cancelled,
+        ..
+    } = *request;
     match mode {
-        PreviewMode::Content => run_preview_content(pathref, coloured, preview, area),
-        PreviewMode::GitLg => run_git(pathref, coloured, preview, area, "lg"),
-        PreviewMode::GitShow => run_git(pathref, coloured, preview, area, "show"),
+        PreviewMode::Content => run_preview_content(pathref, preview, request),
+        PreviewMode::Tree => run_tree(pathref, preview, request),
+        PreviewMode::GitLg => run_git(pathref, style.coloured, preview, area, "lg", cancelled),
+        PreviewMode::GitShow => run_git(pathref, style.coloured, preview, area, "show", cancelled),
+        PreviewMode::WhyIgnored => run_why_ignored(pathref, preview),
+        PreviewMode::Follow => run_follow(pathref, preview, area, cancelled),
     }
 }
 
-fn run_preview_content(
+fn run_tree(
+    pathref: impl AsRef<Path>,
+    preview: Arc<Mutex<PreviewedData>>,
+    request: &PreviewRequest,
+) -> Result<()> {
+    let PreviewRequest {
+        area, cancelled, ..
+    } = *request;
+    let path = pathref.as_ref();
+    let format = match data_tree::Format::of(path) {
+        Some(format) if path.is_file() => format,
+        // nothing to parse, but the content is better than nothing
+        _ => return run_preview_content(path, preview, request),
+    };
+
+    preview.lock().expect("panic").command = PreviewCommand::Custom("tree".to_string());
+    stream_some(fs::File::open(path)?, Arc::clone(&preview), cancelled)?;
+    if cancelled.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let mut data = preview.lock().expect("panic");
+    let complete = fs::metadata(path).map_or(true, |m| m.len() <= data.content.len() as u64);
+    match Tree::parse(format, &data.content, complete, path, area.height) {
+        Ok(mut tree) => {
+            data.render = Some(tree.render());
+            data.tree = Some(tree);
+        }
+        Err(e) => data.render = Some(e.render(&data.content, path)),
+    }
+    Ok(())
+}
+
+/// How much of the end of the file to keep around while following
+const FOLLOW_WINDOW: u64 = 256 * 1024;
+
+/// Like `tail -f`: show the end of the file, and keep reading as it grows,
+/// until the preview is cancelled.
+fn run_follow(
     pathref: impl AsRef<Path>,
-    coloured: bool,
     preview: Arc<Mutex<PreviewedData>>,
     area: URect,
+    cancelled: &AtomicBool,
 ) -> Result<()> {
     let path = pathref.as_ref();
+    preview.lock().expect("panic").command = PreviewCommand::Custom("tail".to_string());
+
+    let mut file = fs::File::open(path)?;
+    let mut pos = file.metadata()?.len().saturating_sub(FOLLOW_WINDOW);
+    let mut skip_partial_line = pos > 0;
+
+    while !cancelled.load(Ordering::Relaxed) {
+        let len = file.metadata()?.len();
+        if len < pos {
+            // truncated, or rotated in place
+            pos = 0;
+            preview.lock().expect("panic").content.clear();
+        }
+
+        if len > pos {
+            file.seek(SeekFrom::Start(pos))?;
+            let mut buf = Vec::with_capacity((len - pos) as usize);
+            (&mut file).take(len - pos).read_to_end(&mut buf)?;
+            pos += buf.len() as u64;
+
+            if skip_partial_line {
+                skip_partial_line = false;
+                let start = buf.iter().position(|&b| b == b'\n').map_or(0, |p| p + 1);
+                buf.drain(..start);
+            }
+
+            let mut preview = preview.lock().expect("panic");
+            preview.content.extend_from_slice(&buf);
+            let excess = preview.content.len().saturating_sub(FOLLOW_WINDOW as usize);
+            if excess > 0 {
+                let cut = preview.content[excess..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(excess, |p| excess + p + 1);
+                preview.content.drain(..cut);
+            }
+
+            let tail = tail_lines(&preview.content, area.height);
+            let mut text = indent(tail, b" ")?;
+            text.lines.insert(0, preview_header("tail", path));
+            preview.render = Some(text);
+        }
+
+        thread::sleep(Duration::from_millis(250));
+    }
+
+    Ok(())
+}
+
+fn tail_lines(content: &[u8], lines: usize) -> &[u8] {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    let start = content
+        .iter()
+        .rev()
+        .enumerate()
+        .filter(|(_, &b)| b == b'\n')
+        .nth(lines.saturating_sub(1))
+        .map_or(0, |(i, _)| content.len() - i);
+    &content[start..]
+}
+
+fn run_why_ignored(pathref: impl AsRef<Path>, preview: Arc<Mutex<PreviewedData>>) -> Result<()> {
+    let path = pathref.as_ref();
+    preview.lock().expect("panic").command = PreviewCommand::Custom("ignore".to_string());
+
+    let mut lines = vec![preview_header("ignore", path), Line::default()];
+
+    let hidden = path
+        .file_name()
+        .map(|name| name.as_encoded_bytes().starts_with(b"."))
+        .unwrap_or_default();
+    if hidden {
+        lines.push(Line::raw("      hidden: name starts with a dot"));
+    }
+
+    match why_ignored(path, path.is_dir()) {
+        Some(reason) => {
+            lines.push(Line::from(vec![
+                Span::raw("     ignored: "),
+                Span::styled(reason.pattern, Style::new().light_red()),
+            ]));
+            lines.push(Line::from(vec![
+                Span::raw("          by: "),
+                Span::styled(reason.source.display().to_string(), Style::new().bold()),
+            ]));
+        }
+        None => lines.push(Line::raw("     not ignored")),
+    }
+
+    preview.lock().expect("panic").render = Some(Text::from(lines));
+
+    Ok(())
+}
+
+fn run_preview_content(
+    pathref: impl AsRef<Path>,
+    preview: Arc<Mutex<PreviewedData>>,
+    request: &PreviewRequest,
+) -> Result<()> {
+    let PreviewRequest {
+        style,
+        area,
+        line,
+        previewers,
+        listing,
+        cancelled,
+        ..
+    } = *request;
+    let path = pathref.as_ref();
+    if let Some((archive, inner)) = archive::split(path) {
+        return run_preview_archive(path, archive, inner, style, preview, area);
+    }
+
     if path.is_file() {
         {
             let mut preview = preview.lock().expect("panic");
             preview.command = PreviewCommand::InterpretFile;
         }
-        stream_some(fs::File::open(path)?, Arc::clone(&preview))?;
+        stream_some(fs::File::open(path)?, Arc::clone(&preview), cancelled)?;
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(());
+        }
 
         let read_content = preview.lock().expect("panic");
         let content = read_content.content.clone();
         drop(read_content);
 
-        let rendered = interpret_file(content, path, area, coloured)?;
+        let size = fs::metadata(path).map_or(content.len() as u64, |m| m.len());
+        let rule = previewers.find(path, false, size, &content);
+        let media = match rule {
+            Some(rule) => matches!(rule.renderer, Renderer::Builtin(Builtin::Media)),
+            None => media::is_media(path),
+        };
+        if media {
+            match show_media(path, &content, size, &preview, cancelled) {
+                Ok(Some(text)) => {
+                    preview.lock().expect("panic").render = Some(text);
+                    return Ok(());
+                }
+                Ok(None) => (),
+                Err(_) if cancelled.load(Ordering::Relaxed) => return Ok(()),
+                Err(e) => info!("preview: ffprobe on {path:?}: {e}"),
+            }
+        }
+
+        // too big to read whole, so it's read a page at a time
+        let kind = match rule.map(|rule| &rule.renderer) {
+            _ if size <= STREAM_LIMIT as u64 => None,
+            None => page_kind(&content, path, style),
+            Some(Renderer::Builtin(Builtin::Bat)) => text_kind(&content),
+            Some(Renderer::Builtin(Builtin::Hexyl)) => Some(Kind::Bytes),
+            Some(_) => None,
+        };
+        if let Some(kind) = kind {
+            return run_paged(path, size, kind, &preview, request);
+        }
+
+        let rendered = match rule {
+            Some(Rule {
+                renderer: Renderer::Command(command),
+                timeout,
+                ..
+            }) => match run_external(path, command, *timeout, &preview, cancelled) {
+                Ok(text) => text,
+                Err(_) if cancelled.load(Ordering::Relaxed) => return Ok(()),
+                Err(e) => {
+                    info!("preview: {command:?} on {path:?}: {e}");
+                    preview.lock().expect("panic").command = PreviewCommand::InterpretFile;
+                    interpret_file(content, path, area, style, line, &preview)?
+                }
+            },
+            Some(Rule {
+                renderer: Renderer::Builtin(builtin),
+                ..
+            }) => render_builtin(*builtin, content, path, area, style, line, &preview)?,
+            None => interpret_file(content, path, area, style, line, &preview)?,
+        };
         preview.lock().expect("panic").render = Some(rendered);
 
         return Ok(());
     }
 
-    let command = "ls";
-    preview.lock().expect("panic").command = PreviewCommand::Custom(command.to_string());
+    if let Some(Rule {
+        renderer: Renderer::Command(command),
+        timeout,
+        ..
+    }) = previewers.find(path, true, 0, &[])
+    {
+        match run_external(path, command, *timeout, &preview, cancelled) {
+            Ok(text) => {
+                preview.lock().expect("panic").render = Some(text);
+                return Ok(());
+            }
+            Err(_) if cancelled.load(Ordering::Relaxed) => return Ok(()),
+            Err(e) => info!("preview: {command:?} on {path:?}: {e}"),
+        }
+    }
+
+    preview.lock().expect("panic").command = PreviewCommand::Custom("tree".to_string());
+    let Some((mut text, readme)) = dir_preview::render(path, style, listing, cancelled) else {
+        return Ok(());
+    };
+    if let Some(readme) = readme {
+        let mut content = Vec::new();
+        let read = fs::File::open(&readme)
+            .and_then(|file| file.take(STREAM_LIMIT as u64).read_to_end(&mut content));
+        if read.is_ok() {
+            text.lines.push(Line::default());
+            text.lines
+                .extend(interpret_file(content, &readme, area, style, None, &preview)?.lines);
+            // the readme's line numbers aren't lines of anything the preview has read
+            preview.lock().expect("panic").source = None;
+        }
+    }
+    preview.lock().expect("panic").render = Some(text);
+    Ok(())
+}
+
+fn run_preview_archive(
+    path: &Path,
+    archive: &Path,
+    inner: &Path,
+    style: PreviewStyle,
+    preview: Arc<Mutex<PreviewedData>>,
+    area: URect,
+) -> Result<()> {
+    preview.lock().expect("panic").command = PreviewCommand::InterpretFile;
+
+    let rendered = match archive::read(archive, inner, STREAM_LIMIT as u64)? {
+        Member::File(content) => {
+            preview.lock().expect("panic").content = content.clone();
+            interpret_file(content, path, area, style, None, &preview)?
+        }
+        Member::Dir(children) => {
+            let mut lines = vec![preview_header("list", path), Line::default()];
+            lines.extend(children.into_iter().map(|(name, (kind, size))| {
+                let name = name.display().to_string();
+                Line::from(vec![
+                    Span::raw(format!("{:>12} ", human_size(size))),
+                    if kind.is_dir() {
+                        Span::styled(format!("{name}/"), Style::new().light_blue())
+                    } else {
+                        Span::raw(name)
+                    },
+                ])
+            }));
+            Text::from(lines)
+        }
+    };
 
-    let spawn = Command::new(command)
-        .args([
-            path.as_os_str(),
-            OsStr::new("-al"),
-            if coloured {
-                OsStr::new("--color=always")
+    preview.lock().expect("panic").render = Some(rendered);
+    Ok(())
+}
+
+/// How a file too big to read whole is paged through, if it's one which would be shown
+/// as text or a hex dump; the rest get what can be made of their start.
+fn page_kind(head: &[u8], showing: &Path, style: PreviewStyle) -> Option<Kind> {
+    match encoding::decode(head) {
+        Some(_)
+            if style.rendered && (markdown::is_markdown(showing) || table::is_table(showing)) =>
+        {
+            None
+        }
+        Some(_) => text_kind(head),
+        None if object_file::is_object(head) || image::guess_format(head).is_ok() => None,
+        None => Some(Kind::Bytes),
+    }
+}
+
+/// By line, as long as a newline is a `\n`, which it isn't in UTF-16.
+fn text_kind(head: &[u8]) -> Option<Kind> {
+    let encoding = encoding::decode_lossy(head).encoding;
+    encoding
+        .is_ascii_compatible()
+        .then_some(Kind::Text(encoding))
+}
+
+/// A page of a file too big to read whole: text from a line, or anything else from
+/// a byte; only that page is read, beyond what finding the line takes.
+fn run_paged(
+    path: &Path,
+    size: u64,
+    kind: Kind,
+    preview: &Mutex<PreviewedData>,
+    request: &PreviewRequest,
+) -> Result<()> {
+    let PreviewRequest {
+        style,
+        area,
+        line,
+        paging,
+        cancelled,
+        ..
+    } = *request;
+    let mut file = fs::File::open(path)?;
+    let (render, content, source, window) = match kind {
+        Kind::Text(encoding) => {
+            // a content match opens a little above the line, as for small files
+            let page = paging
+                .page
+                .or_else(|| line.map(|line| Page::Line(line.saturating_sub(5).max(1))))
+                .unwrap_or(Page::Line(1));
+            let index = paging.indexes.get(path);
+            let mut index = index.lock().expect("panic");
+            let first = match page {
+                Page::Line(first) => first,
+                Page::Byte(offset) => index.line_at(&mut file, offset, cancelled)?,
+                // the last screenful, less the header
+                Page::Percent(100) => {
+                    (index.lines(&mut file, cancelled)? + 2).saturating_sub(paging.rows)
+                }
+                Page::Percent(percent) => {
+                    let offset = size * u64::from(percent) / 100;
+                    index.line_at(&mut file, offset, cancelled)?
+                }
+            };
+            let (first, offset) = index.start(&mut file, first, cancelled)?;
+            drop(index);
+
+            let mut content = read_at(&mut file, offset, PAGE_BYTES)?;
+            let end = offset + content.len() as u64 >= size;
+            if !end {
+                // only whole lines, so the next page starts on one
+                if let Some(last) = memchr::memrchr(b'\n', &content) {
+                    content.truncate(last + 1);
+                }
+            }
+            let decoded = if offset == 0 {
+                encoding::decode_lossy(&content)
             } else {
-                OsStr::new("--color=never")
-            },
-        ])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::null())
-        .spawn()?;
+                encoding::decode_as(&content, encoding)
+            };
+            let text = decoded.text.replace('\r', "");
 
-    let mut buf = Vec::with_capacity(4096);
-    spawn
-        .stdout
-        .expect("piped")
-        .take(1024 * 1024)
-        .read_to_end(&mut buf)?;
+            let mut writer = LineStopFmtWrite::new(area.height);
+            let mut printer = bat_printer(&text, path, area, style.coloured);
+            if let Some(line) = line.filter(|&line| line >= first) {
+                printer.highlight(line + 1 - first);
+            }
+            // expecting an unnamed error on writer full
+            let _ = printer.print_with_writer(Some(&mut writer));
+            drop(printer);
+            let full = writer.is_full();
+            let mut render = writer.inner.into_text()?;
+            renumber(&mut render, first - 1);
+            let header = with_encoding(preview_header("bat", path), &decoded);
+            render.lines.insert(0, with_position(header, offset, size));
+
+            let lines = memchr::memchr_iter(b'\n', &content).count().max(1);
+            let window = Window {
+                first: Page::Line(first),
+                next: (!end && !full).then_some(Page::Line(first + lines)),
+                step: 1,
+            };
+            (render, content, Some(text), window)
+        }
+        Kind::Bytes => {
+            let step = 8 * hex_panels(area) as u64;
+            let offset = match paging.page.unwrap_or(Page::Byte(0)) {
+                Page::Byte(offset) => offset.min(size),
+                Page::Line(_) => 0,
+                // the last screenful, less the header and hexyl's borders
+                Page::Percent(100) => {
+                    let lines = size.saturating_sub(1) / step;
+                    lines.saturating_sub(paging.rows.saturating_sub(4) as u64) * step
+                }
+                Page::Percent(percent) => size * u64::from(percent) / 100 / step * step,
+            };
+            let content = read_at(&mut file, offset, PAGE_BYTES)?;
+            let end = offset + content.len() as u64 >= size;
 
-    let mut text = indent(&buf, b"     ")?;
-    text.lines.insert(0, preview_header("ls", path));
+            let (mut render, full) = hexdump(&content, area, style.coloured, offset)?;
+            let header = with_position(preview_header("hexyl", path), offset, size);
+            render.lines.insert(0, header);
+            if offset == 0 {
+                with_file_type(&mut render, &content, &path);
+            }
+            let window = Window {
+                first: Page::Byte(offset),
+                next: (!end && !full).then_some(Page::Byte(offset + content.len() as u64)),
+                step,
+            };
+            (render, content, None, window)
+        }
+    };
 
-    let mut preview = preview.lock().expect("panic");
-    preview.render = Some(text);
-    preview.content = buf;
+    let mut data = preview.lock().expect("panic");
+    data.content = content;
+    data.render = Some(render);
+    data.source = source;
+    data.window = Some(window);
     Ok(())
 }
 
+fn read_at(file: &mut fs::File, offset: u64, limit: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut content = Vec::new();
+    file.by_ref().take(limit).read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Move bat's line numbers on, for a page which doesn't start at the first line,
+/// widening the gutter to fit the last of them.
+fn renumber(render: &mut Text, by: usize) {
+    if by == 0 {
+        return;
+    }
+    let Some(last) = render
+        .lines
+        .iter()
+        .filter_map(gutter_number)
+        .map(|(_, n)| n)
+        .max()
+    else {
+        return;
+    };
+    let digits = |n: usize| n.to_string().len();
+    // bat pads to 4 columns, unless a number needs more
+    let extra = digits(last + by).max(4) - digits(last).max(4);
+    for line in &mut render.lines {
+        match gutter_number(line) {
+            Some((i, n)) => {
+                let span = &mut line.spans[i];
+                let content = span.content.as_ref();
+                let number = content.trim_start_matches(' ');
+                let old = content.len() - number.len() + digits(n);
+                let width = old + extra;
+                span.content = format!("{:>width$}{}", n + by, &number[digits(n)..]).into();
+            }
+            // a wrapped line's empty gutter has to widen with the rest
+            None if extra > 0 => line.spans.insert(0, Span::raw(" ".repeat(extra))),
+            None => (),
+        }
+    }
+}
+
+/// The span of a line of bat's output with its line number in, and the number;
+/// None for a wrapped line, whose gutter is empty, so its content is further in.
+fn gutter_number(line: &Line) -> Option<(usize, usize)> {
+    let mut indent = 0;
+    for (i, span) in line.spans.iter().enumerate() {
+        let content = span.content.as_ref();
+        let number = content.trim_start_matches(' ');
+        indent += content.len() - number.len();
+        if number.is_empty() {
+            continue;
+        }
+        let digits = number.bytes().take_while(u8::is_ascii_digit).count();
+        if indent >= 4 || digits == 0 {
+            return None;
+        }
+        return number[..digits].parse().ok().map(|n| (i, n));
+    }
+    None
+}
+
+/// How far through a big file the page starts.
+fn with_position(mut header: Line<'static>, offset: u64, size: u64) -> Line<'static> {
+    header.spans.push(Span::styled(
+        format!("  {}% of {}", offset * 100 / size.max(1), human_size(size)),
+        Style::new().dark_gray(),
+    ));
+    header
+}
+
 fn indent(buf: &[u8], with: &[u8]) -> Result<Text<'static>> {
     let mut indented = Vec::with_capacity(buf.len() * 2);
     for line in buf.split(|&b| b == b'\n') {
@@ -128,10 +673,14 @@ fn indent(buf: &[u8], with: &[u8]) -> Result<Text<'static>> {
     Ok(indented.into_text()?)
 }
 
-fn stream_some(reader: impl Read, preview: Arc<Mutex<PreviewedData>>) -> Result<()> {
+fn stream_some(
+    reader: impl Read,
+    preview: Arc<Mutex<PreviewedData>>,
+    cancelled: &AtomicBool,
+) -> Result<()> {
     let mut reader = reader;
     let mut buf = [0u8; 1024];
-    loop {
+    while !cancelled.load(Ordering::Relaxed) {
         let bytes = reader.read(&mut buf)?;
         if bytes == 0 {
             break;
@@ -139,7 +688,7 @@ fn stream_some(reader: impl Read, preview: Arc<Mutex<PreviewedData>>) -> Result<
         let buf = &buf[..bytes];
         let mut preview = preview.lock().expect("panic");
         preview.content.extend(buf);
-        if preview.content.len() > 1024 * 1024 {
+        if preview.content.len() > STREAM_LIMIT {
             break;
         }
     }
@@ -147,82 +696,349 @@ fn stream_some(reader: impl Read, preview: Arc<Mutex<PreviewedData>>) -> Result<
 }
 
 fn interpret_file(
-    mut content: Vec<u8>,
+    content: Vec<u8>,
     showing: impl AsRef<Path>,
     area: URect,
-    coloured: bool,
+    style: PreviewStyle,
+    line: Option<usize>,
+    preview: &Mutex<PreviewedData>,
 ) -> Result<Text<'static>> {
-    use ansi_to_tui::IntoText as _;
+    let Some(decoded) = encoding::decode(&content) else {
+        if object_file::is_object(&content) && showing.as_ref().is_file() {
+            return show_object(&content, &showing, area, style.coloured);
+        }
+        let image = show_image(&showing, &content, area, style, preview)?;
+        return match image {
+            Some(image_content) => Ok(image_content),
+            None => show_binary(&content, &showing, area, style.coloured),
+        };
+    };
+    // a line to show, from a search hit, needs the source to find it in
+    if style.rendered && line.is_none() && markdown::is_markdown(showing.as_ref()) {
+        Ok(show_markdown(&decoded, showing, area, style.coloured))
+    } else if style.rendered && table::is_table(showing.as_ref()) {
+        let truncated = content.len() > STREAM_LIMIT;
+        Ok(show_table(
+            &decoded,
+            truncated,
+            showing,
+            area,
+            style.coloured,
+            preview,
+        ))
+    } else {
+        show_text(decoded, showing, area, style.coloured, line, preview)
+    }
+}
 
-    Ok(match content_inspector::inspect(&content) {
-        ContentType::BINARY => match show_image(&showing, area)? {
-            Some(image_content) => image_content,
-            None => show_binary(&content, &showing, area, coloured)?,
+/// A built-in renderer, chosen by a rule rather than by looking at the content.
+fn render_builtin(
+    builtin: Builtin,
+    content: Vec<u8>,
+    showing: impl AsRef<Path>,
+    area: URect,
+    style: PreviewStyle,
+    line: Option<usize>,
+    preview: &Mutex<PreviewedData>,
+) -> Result<Text<'static>> {
+    let truncated = content.len() > STREAM_LIMIT;
+    let text = || encoding::decode_lossy(&content);
+    match builtin {
+        Builtin::Bat => show_text(text(), showing, area, style.coloured, line, preview),
+        Builtin::Hexyl => show_binary(&content, &showing, area, style.coloured),
+        Builtin::Image => match show_image(&showing, &content, area, style, preview)? {
+            Some(image_content) => Ok(image_content),
+            None => interpret_file(content, showing, area, style, line, preview),
         },
-        _ => {
-            let mut writer = LineStopFmtWrite::new(area.height);
-            content.retain(|&b| b != b'\r');
-            // expecting an unnamed error on writer full
-            let _ = bat::PrettyPrinter::new()
-                .input(bat::Input::from_bytes(&content).name(&showing))
-                .header(false)
-                .colored_output(coloured)
-                .term_width(area.width)
-                .tab_width(Some(2))
-                .line_numbers(true)
-                .use_italics(false)
-                .print_with_writer(Some(&mut writer));
-            let mut ret = writer.inner.into_text()?;
-            ret.lines.insert(0, preview_header("bat", showing));
-            ret
+        Builtin::Markdown if style.rendered && line.is_none() => {
+            Ok(show_markdown(&text(), showing, area, style.coloured))
         }
-    })
+        Builtin::Markdown => show_text(text(), showing, area, style.coloured, line, preview),
+        Builtin::Table if style.rendered => Ok(show_table(
+            &text(),
+            truncated,
+            showing,
+            area,
+            style.coloured,
+            preview,
+        )),
+        Builtin::Table => show_text(text(), showing, area, style.coloured, line, preview),
+        Builtin::Ls => interpret_file(content, showing, area, style, line, preview),
+        // ffprobe couldn't say
+        Builtin::Media => interpret_file(content, showing, area, style, line, preview),
+        Builtin::Object if showing.as_ref().is_file() => {
+            show_object(&content, &showing, area, style.coloured)
+        }
+        Builtin::Object => interpret_file(content, showing, area, style, line, preview),
+    }
+}
+
+fn show_text(
+    decoded: Decoded,
+    showing: impl AsRef<Path>,
+    area: URect,
+    coloured: bool,
+    line: Option<usize>,
+    preview: &Mutex<PreviewedData>,
+) -> Result<Text<'static>> {
+    use bat::line_range::{LineRange, LineRanges};
+
+    let mut writer = LineStopFmtWrite::new(area.height);
+    // the header says what the line endings were
+    let content = decoded.text.replace('\r', "");
+    let mut printer = bat_printer(&content, &showing, area, coloured);
+    if let Some(line) = line {
+        // start a little above, so the line is in view with some context
+        let from = line.saturating_sub(5).max(1);
+        printer
+            .highlight(line)
+            .line_ranges(LineRanges::from(vec![LineRange::new(
+                from,
+                from + area.height,
+            )]));
+    }
+    // expecting an unnamed error on writer full
+    let _ = printer.print_with_writer(Some(&mut writer));
+    drop(printer);
+    let mut ret = writer.inner.into_text()?;
+    ret.lines
+        .insert(0, with_encoding(preview_header("bat", showing), &decoded));
+    preview.lock().expect("panic").source = Some(content);
+    Ok(ret)
+}
+
+fn bat_printer<'a>(
+    content: &'a str,
+    showing: impl AsRef<Path>,
+    area: URect,
+    coloured: bool,
+) -> bat::PrettyPrinter<'a> {
+    let mut printer = bat::PrettyPrinter::new();
+    printer
+        .input(bat::Input::from_bytes(content.as_bytes()).name(showing))
+        .header(false)
+        .colored_output(coloured)
+        .term_width(area.width)
+        .tab_width(Some(2))
+        .line_numbers(true)
+        .use_italics(false);
+    printer
+}
+
+fn show_markdown(
+    decoded: &Decoded,
+    showing: impl AsRef<Path>,
+    area: URect,
+    coloured: bool,
+) -> Text<'static> {
+    let mut ret = markdown::render(decoded.text.as_bytes(), area, coloured);
+    ret.lines
+        .insert(0, with_encoding(preview_header("md", showing), decoded));
+    ret
+}
+
+fn show_table(
+    decoded: &Decoded,
+    truncated: bool,
+    showing: impl AsRef<Path>,
+    area: URect,
+    coloured: bool,
+    preview: &Mutex<PreviewedData>,
+) -> Text<'static> {
+    preview.lock().expect("panic").pinned = table::PINNED;
+    let mut ret = table::render(
+        decoded.text.as_bytes(),
+        showing.as_ref(),
+        area,
+        coloured,
+        truncated,
+    );
+    ret.lines[0] = with_encoding(ret.lines[0].clone(), decoded);
+    ret
+}
+
+fn with_encoding(mut header: Line<'static>, decoded: &Decoded) -> Line<'static> {
+    header.spans.push(Span::styled(
+        format!("  {}", decoded.describe()),
+        Style::new().dark_gray(),
+    ));
+    header
+}
+
+/// Run a rule's command on the path, failing if it does, or takes too long.
+fn run_external(
+    path: &Path,
+    command: &str,
+    timeout: Duration,
+    preview: &Mutex<PreviewedData>,
+    cancelled: &AtomicBool,
+) -> Result<Text<'static>> {
+    let name = command
+        .split_whitespace()
+        .next()
+        .unwrap_or("sh")
+        .to_string();
+    preview.lock().expect("panic").command = PreviewCommand::Custom(name.clone());
+
+    let printed = read_command(
+        &mut shell_command(command, path),
+        true,
+        Some(timeout),
+        cancelled,
+    )?;
+    if !printed.ok {
+        bail!("{}", String::from_utf8_lossy(&printed.buf).trim());
+    }
+
+    let mut text = indent(&printed.buf, b" ")?;
+    text.lines.insert(0, preview_header(&name, path));
+    Ok(text)
 }
 
 fn show_image<'a>(
     showing: &impl AsRef<Path>,
+    content: &[u8],
     area: URect,
+    style: PreviewStyle,
+    preview: &Mutex<PreviewedData>,
 ) -> Result<Option<Text<'a>>, anyhow::Error> {
     use termimage::ops;
 
-    let image: Option<DynamicImage> = {
+    let image: Option<DynamicImage> = if !showing.as_ref().is_file() {
+        // e.g. inside an archive; all we have is the start of it
+        image::load_from_memory(content).ok()
+    } else {
         let description = (String::new(), showing.as_ref().to_path_buf());
-        if let Some(format) = ops::guess_format(&description).ok() {
+        if let Ok(format) = ops::guess_format(&description) {
             ops::load_image(&description, format).ok()
         } else {
             None
         }
     };
 
-    if let Some(image) = image {
-        let size = (area.width as u32, area.height as u32);
-        let img_s = ops::image_resized_size(image.dimensions(), size, true);
-        let resized = ops::resize_image(&image, img_s);
+    let Some(image) = image else {
+        return Ok(None);
+    };
 
-        let mut writer = LineStopIoWrite::new(area.height);
-        ops::write_ansi_truecolor(&mut writer, &resized);
+    let exif = photo::read_exif(showing.as_ref(), content);
+    let mut lines = vec![preview_header("image", showing)];
+    lines.extend(photo::details(&image, content, exif.as_ref()));
+    lines.push(Line::default());
+    // the image goes under the details
+    let above = lines.len();
+    let height = area.height.saturating_sub(above);
 
-        Ok(Some(writer.inner.into_text()?))
-    } else {
-        Ok(None)
+    let cells = (area.width as u16, height as u16);
+    match graphics::encode(style.graphics, &image, cells, above as u16) {
+        Ok(graphic) => {
+            preview.lock().expect("panic").graphic = graphic.map(|g| (Arc::new(g), above))
+        }
+        Err(e) => info!(
+            "{:?} image for {:?}: {e:?}",
+            style.graphics,
+            showing.as_ref()
+        ),
+    }
+
+    let size = (area.width as u32, height as u32);
+    let img_s = ops::image_resized_size(image.dimensions(), size, true);
+    let resized = ops::resize_image(&image, img_s);
+
+    let mut writer = LineStopIoWrite::new(height);
+    ops::write_ansi_truecolor(&mut writer, &resized);
+
+    let mut text = writer.inner.into_text()?;
+    text.lines.splice(0..0, lines);
+    Ok(Some(text))
+}
+
+/// Container details and tags, from ffprobe, or what the start of the file says it is
+/// if there's no ffprobe; `None` if neither can tell.
+fn show_media(
+    showing: &Path,
+    head: &[u8],
+    size: u64,
+    preview: &Mutex<PreviewedData>,
+    cancelled: &AtomicBool,
+) -> Result<Option<Text<'static>>> {
+    preview.lock().expect("panic").command = PreviewCommand::Custom("ffprobe".to_string());
+    let mut command = Command::new("ffprobe");
+    command
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_format", "-show_streams"])
+        .arg(showing)
+        .stdin(Stdio::null());
+    let printed = match read_command(&mut command, false, Some(MEDIA_TIMEOUT), cancelled) {
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::NotFound) =>
+        {
+            preview.lock().expect("panic").command = PreviewCommand::InterpretFile;
+            // still better than a hex dump
+            return Ok(media::render_detected(head, size, showing));
+        }
+        printed => printed?,
+    };
+    if !printed.ok {
+        preview.lock().expect("panic").command = PreviewCommand::InterpretFile;
+        return Ok(None);
+    }
+    media::render(&printed.buf, showing).map(Some)
+}
+
+/// The structure of an executable or library, or the hex if it won't parse after all.
+fn show_object(
+    content: &[u8],
+    showing: &impl AsRef<Path>,
+    area: URect,
+    coloured: bool,
+) -> Result<Text<'static>> {
+    match object_file::render(showing.as_ref()) {
+        Ok(text) => Ok(text),
+        Err(e) => {
+            info!("object: {:?}: {e}", showing.as_ref());
+            show_binary(content, showing, area, coloured)
+        }
     }
 }
 
 fn show_binary<'a>(
-    content: &Vec<u8>,
+    content: &[u8],
     showing: &impl AsRef<Path>,
     area: URect,
     coloured: bool,
 ) -> Result<Text<'a>, anyhow::Error> {
+    let (mut ret, _) = hexdump(content, area, coloured, 0)?;
+    ret.lines.insert(0, preview_header("hexyl", showing));
+    with_file_type(&mut ret, content, showing);
+    Ok(ret)
+}
+
+/// hexyl's dump of what was read from `offset`, and whether it filled the area.
+fn hexdump(
+    content: &[u8],
+    area: URect,
+    coloured: bool,
+    offset: u64,
+) -> Result<(Text<'static>, bool)> {
     let mut v = LineStopIoWrite::new(area.height);
-    let panels = (area.width.saturating_sub(10) / 35).max(1);
     let _ = hexyl::PrinterBuilder::new(&mut v)
-        .num_panels(panels as u64)
+        .num_panels(hex_panels(area) as u64)
         .show_color(coloured)
         .build()
+        .display_offset(offset)
         .print_all(io::Cursor::new(content));
-    let mut ret = v.inner.into_text()?;
-    ret.lines.insert(0, preview_header("hexyl", showing));
+    let full = v.is_full();
+    Ok((v.inner.into_text()?, full))
+}
+
+/// As many as fit; each is 8 bytes a line.
+fn hex_panels(area: URect) -> usize {
+    (area.width.saturating_sub(10) / 35).max(1)
+}
+
+/// Say what sort of file it is above the dump, if its start says.
+fn with_file_type(ret: &mut Text, content: &[u8], showing: &impl AsRef<Path>) {
     let media_type = file_type::FileType::from_bytes(content);
     if !media_type.extensions().is_empty() {
         ret.lines.insert(0, preview_header("file", showing));
@@ -232,7 +1048,6 @@ fn show_binary<'a>(
         );
         ret.lines.insert(2, Line::default());
     }
-    Ok(ret)
 }
 
 pub fn preview_header(command: &str, showing: impl AsRef<Path>) -> Line<'static> {
@@ -243,15 +1058,151 @@ pub fn preview_header(command: &str, showing: impl AsRef<Path>) -> Line<'static>
     ])
 }
 
+/// What a command printed, and whether it was happy about it
+struct Printed {
+    buf: Vec<u8>,
+    ok: bool,
+}
+
+/// Up to a megabyte of a command's output (and then its errors, if wanted),
+/// killing it if we're cancelled, or it runs out of time, while it's still going.
+fn read_command(
+    command: &mut Command,
+    with_stderr: bool,
+    timeout: Option<Duration>,
+    cancelled: &AtomicBool,
+) -> Result<Printed> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(if with_stderr {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .spawn()?;
+
+    let stdout = child.stdout.take().expect("piped");
+    let stderr = child.stderr.take();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::with_capacity(4096);
+        let read = stdout
+            .take(1024 * 1024)
+            .read_to_end(&mut buf)
+            .and_then(|_| match stderr {
+                Some(stderr) => stderr.take(1024 * 1024).read_to_end(&mut buf),
+                None => Ok(0),
+            });
+        let _ = tx.send(read.map(|_| buf));
+    });
+
+    loop {
+        match rx.recv_timeout(Duration::from_millis(50)) {
+            Ok(read) => {
+                let ok = child.wait().is_ok_and(|status| status.success());
+                return Ok(Printed { buf: read?, ok });
+            }
+            Err(RecvTimeoutError::Timeout)
+                if !cancelled.load(Ordering::Relaxed)
+                    && deadline.is_none_or(|deadline| Instant::now() < deadline) => {}
+            Err(_) => {
+                // closes the pipes, so the reader gives up too
+                let _ = child.kill();
+                let _ = child.wait();
+                if let (Some(timeout), false) = (timeout, cancelled.load(Ordering::Relaxed)) {
+                    bail!("timed out after {timeout:?}");
+                }
+                return Ok(Printed {
+                    buf: Vec::new(),
+                    ok: false,
+                });
+            }
+        }
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
 impl Previews {
+    pub fn new(previewers: Arc<Previewers>) -> Self {
+        Self {
+            inner: VecDeque::new(),
+            pool: Pool::new(WORKERS),
+            previewers,
+            line_indexes: Arc::default(),
+        }
+    }
+
+    /// Start on a preview; it'll be looked after in the pool, unless it runs forever.
+    pub fn run(&mut self, preview: &Preview, job: impl FnOnce() + Send + 'static) {
+        if preview.mode == PreviewMode::Follow {
+            thread::spawn(job);
+        } else {
+            self.pool.submit(Arc::clone(&preview.cancelled), job);
+        }
+    }
+
+    /// Drop the least recently used previews until we're within budget,
+    /// keeping the newest, however big it is.
+    pub fn evict(&mut self) {
+        let mut total = self.inner.iter().map(Preview::bytes).sum::<usize>();
+        while total > CACHE_BYTES && self.inner.len() > 1 {
+            if let Some(evicted) = self.inner.pop_front() {
+                total -= evicted.bytes();
+            }
+        }
+    }
+
     pub fn is_scanning(&self) -> bool {
-        self.inner.iter().any(|v| !v.worker.is_finished())
+        self.inner.iter().any(|v| v.is_loading())
     }
 
     pub fn would_flicker(&self) -> bool {
         self.inner
             .iter()
-            .any(|v| v.started.elapsed() < Duration::from_millis(100) && !v.worker.is_finished())
+            .any(|v| v.started.elapsed() < Duration::from_millis(100) && v.is_loading())
+    }
+}
+
+impl Preview {
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// roughly; the render is counted by its text, plus a bit for each span, and
+    /// an image by what it is to the terminal
+    fn bytes(&self) -> usize {
+        let Ok(data) = self.data.lock() else {
+            return 0;
+        };
+        let render = data.render.as_ref().map_or(0, |text| {
+            text.lines
+                .iter()
+                .flat_map(|line| &line.spans)
+                .map(|span| span.content.len() + mem::size_of::<Span>())
+                .sum()
+        });
+        let graphic = data
+            .graphic
+            .as_ref()
+            .map_or(0, |(graphic, _)| graphic.bytes());
+        data.content.len() + render + graphic
+    }
+
+    /// followed previews never finish, but they aren't waiting for anything either
+    fn is_loading(&self) -> bool {
+        !self.is_finished()
+            && (self.mode != PreviewMode::Follow
+                || self
+                    .data
+                    .lock()
+                    .map(|d| d.render.is_none())
+                    .unwrap_or(false))
     }
 }
 
@@ -261,36 +1212,27 @@ fn run_git(
     preview: Arc<Mutex<PreviewedData>>,
     _area: URect,
     sub_cmd: &str,
+    cancelled: &AtomicBool,
 ) -> Result<()> {
     preview.lock().expect("panic").command = PreviewCommand::Custom(format!("g {sub_cmd}"));
 
-    let spawn = Command::new("git")
-        .args([
-            OsStr::new(sub_cmd),
-            if coloured {
-                OsStr::new("--color=always")
-            } else {
-                OsStr::new("--color=never")
-            },
-            path.as_ref().as_os_str(),
-        ])
-        .current_dir(path.as_ref().parent().ok_or_else(|| anyhow!("no parent"))?)
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()?;
-
-    let mut buf = Vec::with_capacity(4096);
-    spawn
-        .stdout
-        .expect("piped")
-        .take(1024 * 1024)
-        .read_to_end(&mut buf)?;
-
-    spawn
-        .stderr
-        .expect("piped")
-        .take(1024 * 1024)
-        .read_to_end(&mut buf)?;
+    let mut buf = read_command(
+        Command::new("git")
+            .args([
+                OsStr::new(sub_cmd),
+                if coloured {
+                    OsStr::new("--color=always")
+                } else {
+                    OsStr::new("--color=never")
+                },
+                path.as_ref().as_os_str(),
+            ])
+            .current_dir(path.as_ref().parent().ok_or_else(|| anyhow!("no parent"))?),
+        true,
+        None,
+        cancelled,
+    )?
+    .buf;
 
     buf.retain(|&b| b != b'\r');
 
@@ -0,0 +1,179 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobMatcher};
+use serde::Deserialize;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which previewer to use for what, from a file like:
+///
+/// ```toml
+/// [[rule]]
+/// extension = "pdf"
+/// command = "pdftotext {} -"
+/// timeout_ms = 2000
+///
+/// [[rule]]
+/// mime = "image/"
+/// max_size = 20_000_000
+/// builtin = "image"
+///
+/// [[rule]]
+/// directories = true
+/// glob = "**/node_modules"
+/// command = "du -sh {}"
+/// ```
+///
+/// The first rule which matches wins; with none, or if the command fails,
+/// the built-in previews decide for themselves.
+#[derive(Default)]
+pub struct Previewers {
+    rules: Vec<Rule>,
+}
+
+pub struct Rule {
+    matches: Match,
+    directories: bool,
+    pub renderer: Renderer,
+    pub timeout: Duration,
+    max_size: Option<u64>,
+}
+
+enum Match {
+    Glob(GlobMatcher),
+    Extension(String),
+    /// a prefix, so `image/` catches all images
+    Mime(String),
+}
+
+pub enum Renderer {
+    /// run through `sh`, with `{}` standing for the path
+    Command(String),
+    Builtin(Builtin),
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Builtin {
+    /// text, highlighted
+    Bat,
+    Hexyl,
+    Image,
+    /// the directory tree
+    Ls,
+    /// rendered, unless the preview is toggled to source
+    Markdown,
+    /// csv and the like, as a table; also unless toggled to source
+    Table,
+    /// audio and video details, from ffprobe
+    Media,
+    /// executables, libraries and object files, taken apart
+    Object,
+}
+
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    glob: Option<String>,
+    extension: Option<String>,
+    mime: Option<String>,
+    #[serde(default)]
+    directories: bool,
+    command: Option<String>,
+    builtin: Option<Builtin>,
+    timeout_ms: Option<u64>,
+    max_size: Option<u64>,
+}
+
+impl Previewers {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("rurt").join("previewers.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        let rules = config
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| Rule::new(rule).with_context(|| format!("rule {}", i + 1)))
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// The rule for a path; `head` is the start of a file, for its type.
+    pub fn find(&self, path: &Path, is_dir: bool, size: u64, head: &[u8]) -> Option<&Rule> {
+        // only worked out if a rule asks
+        let mut media_types = None;
+        self.rules.iter().find(|rule| {
+            if rule.directories != is_dir || rule.max_size.is_some_and(|max| size > max) {
+                return false;
+            }
+            match &rule.matches {
+                Match::Glob(glob) => glob.is_match(path),
+                Match::Extension(ext) => path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case(ext)),
+                Match::Mime(prefix) => media_types
+                    .get_or_insert_with(|| file_type::FileType::from_bytes(head).media_types())
+                    .iter()
+                    .any(|t| t.starts_with(prefix.as_str())),
+            }
+        })
+    }
+}
+
+/// A command line as the config writes them, run through `sh` with `{}` standing for
+/// the path, or the path on the end if there's no `{}`. The path goes in as an
+/// argument, so it never needs quoting.
+pub fn shell_command(command: &str, path: &Path) -> Command {
+    shell_command_with(command, [path.as_os_str()])
+}
+
+/// As [`shell_command`], with `{}` standing for all of `args`, in order.
+pub fn shell_command_with<'a>(command: &str, args: impl IntoIterator<Item = &'a OsStr>) -> Command {
+    let script = if command.contains("{}") {
+        command.replace("{}", "\"$@\"")
+    } else {
+        format!("{command} \"$@\"")
+    };
+    let mut sh = Command::new("sh");
+    sh.arg("-c").arg(script).arg("sh").args(args);
+    sh
+}
+
+impl Rule {
+    fn new(config: RuleConfig) -> Result<Self> {
+        let matches = match (config.glob, config.extension, config.mime) {
+            (Some(glob), None, None) => Match::Glob(Glob::new(&glob)?.compile_matcher()),
+            (None, Some(ext), None) => Match::Extension(ext.trim_start_matches('.').to_string()),
+            (None, None, Some(mime)) => Match::Mime(mime),
+            _ => bail!("needs exactly one of `glob`, `extension` or `mime`"),
+        };
+        let renderer = match (config.command, config.builtin) {
+            (Some(command), None) => Renderer::Command(command),
+            (None, Some(builtin)) => Renderer::Builtin(builtin),
+            _ => bail!("needs exactly one of `command` or `builtin`"),
+        };
+        Ok(Self {
+            matches,
+            directories: config.directories,
+            renderer,
+            timeout: config
+                .timeout_ms
+                .map_or(DEFAULT_TIMEOUT, Duration::from_millis),
+            max_size: config.max_size,
+        })
+    }
+}
@@ -1,20 +1,27 @@
 use crate::action::{handle_action, matches_binding, Action, ActionResult};
 use crate::alt_screen::enter_alt_screen;
+use crate::cleanup::Disposal;
+use crate::du::DiskUsage;
 use crate::git_but_bad::{git_log_matches, Logs};
-use crate::preview::Previews;
+use crate::graphics::{self, Placement};
+use crate::ignores::Ignores;
+use crate::photo::CaptureDates;
+use crate::preview::{PreviewStyle, Previews};
 use crate::snapped::revalidate_cursor;
 use crate::store::Store;
 use crate::tui_log::LogWidgetState;
-use crate::ui_state::{CommandPalette, Cursor, SortedItems, Ui};
+use crate::ui_state::{CommandPalette, Cursor, PreviewFocus, SortOrder, SortedItems, Ui};
 use crate::{draw, filter_bindings, snapped, ui_state, App};
 use anyhow::Result;
 use arboard::Clipboard;
 use crossterm::event;
-use crossterm::event::{Event, KeyCode};
+use crossterm::event::{Event, KeyCode, KeyModifiers};
 use log::info;
 use lscolors::LsColors;
 use nucleo::pattern::{CaseMatching, Normalization};
 use ratatui::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::io::stderr;
 use std::process::ExitCode;
 use std::sync::{Arc, Mutex};
@@ -22,6 +29,14 @@ use std::time::{Duration, Instant};
 use tui_input::backend::crossterm::to_input_request;
 use tui_input::Input;
 
+/// How often the branch, and how far it is from upstream, is looked at again
+const SUMMARY_REFRESH: Duration = Duration::from_secs(5);
+
+/// How long the watched files have to be left alone before git status is redone,
+/// and how long it's put off at most
+const GIT_QUIET: Duration = Duration::from_secs(1);
+const GIT_BUSY: Duration = Duration::from_secs(5);
+
 pub fn run(
     store: &mut Store,
     app: &mut App,
@@ -40,22 +55,72 @@ pub fn run(
         prompt: format!("{}> ", app.here.display()),
         active: true,
         sorted_items: SortedItems::default(),
-        previews: Previews::default(),
+        previews: Previews::new(Arc::clone(&app.previewers)),
         git_info: app.git_info(),
+        ignores: Ignores::default(),
         bad_git_log: Logs::default(),
         preview_cursor: 0,
-        preview_colours: true,
+        preview_page: None,
+        preview_style: PreviewStyle {
+            coloured: true,
+            rendered: true,
+            hidden: app.read_opts.show_hidden,
+            ignored: app.read_opts.show_ignored,
+            graphics: app.view_opts.graphics,
+        },
         ls_colors: LsColors::from_env().unwrap_or_default(),
         command_palette: CommandPalette::default(),
+        disk_usage: None,
+        sort: SortOrder::default(),
+        capture_dates: CaptureDates::default(),
+        cleanup: None,
+        marked: HashSet::new(),
+        grep: false,
+        preview_focus: PreviewFocus::default(),
+        placement: RefCell::default(),
     };
 
     store.start_scan(app)?;
 
+    // status is for the whole repo, so it waits for a burst of writes to be over
+    let mut git_dirty = false;
+    let mut last_change = Instant::now();
+    let mut last_git_refresh = Instant::now();
+    let mut last_summary_refresh = Instant::now();
+    // the image on screen, and the size of the screen it went on
+    let mut placed: Option<(Placement, Rect)> = None;
+
     loop {
         maybe_update_target_dir(app);
 
         store.nucleo.tick(10);
 
+        let (changes, restarted) = store.poll_watch(app)?;
+        if restarted {
+            ui.sorted_items.clear();
+        }
+        if !changes.is_empty() {
+            ui.previews
+                .inner
+                .retain(|p| !changes.all().any(|c| p.showing.starts_with(c)));
+            git_dirty = true;
+            last_change = Instant::now();
+            store.nucleo.tick(10);
+        }
+        // a file which never stops being written still gets its marker, eventually
+        if git_dirty && (last_change.elapsed() > GIT_QUIET || last_git_refresh.elapsed() > GIT_BUSY)
+        {
+            ui.git_info = app.git_info();
+            git_dirty = false;
+            last_git_refresh = Instant::now();
+        }
+        if last_summary_refresh.elapsed() > SUMMARY_REFRESH {
+            if let Some(git) = &ui.git_info {
+                git.refresh_summary();
+            }
+            last_summary_refresh = Instant::now();
+        }
+
         ui.active = store.is_scanning() || ui.previews.is_scanning();
 
         if ui.active && (store.would_flicker() || ui.previews.would_flicker()) {
@@ -67,17 +132,52 @@ pub fn run(
 
         let snap = store.nucleo.snapshot();
 
-        let last_area = terminal
-            .draw(|f| {
-                let area = draw::setup_screen(f.area(), &app.view_opts);
-                ui_state::trigger_right_pane(&mut ui, app.view_opts, area.side_pane);
+        if let Some(du) = &ui.disk_usage {
+            if ui.sort != SortOrder::Name && du.should_resort() {
+                ui.sorted_items.clear();
+            }
+        }
+        if ui.sort == SortOrder::Taken && ui.capture_dates.should_resort() {
+            ui.sorted_items.clear();
+        }
+
+        ui.placement.replace(None);
+        let frame = terminal.draw(|f| {
+            let area = draw::setup_screen(f.area(), &app.view_opts);
+            ui_state::trigger_right_pane(&mut ui, app.view_opts, area.side_pane);
 
-                let items_required = area.items_required(&app.view_opts);
-                revalidate_cursor(&mut ui, snap, items_required);
-                let items = snapped::ui_item_range(&mut ui, snap, items_required);
-                draw::draw_ui(f, area, &ui, &app, &items, log_state.clone())
-            })?
-            .area;
+            let items_required = area.items_required(&app.view_opts);
+            revalidate_cursor(&mut ui, snap, items_required);
+            let items = snapped::ui_item_range(&mut ui, snap, items_required);
+            draw::draw_ui(f, area, &ui, app, &items, log_state.clone())
+        })?;
+        let last_area = frame.area;
+
+        let wanted = ui.placement.borrow().clone().map(|p| (p, last_area));
+        // what's now on screen where the old image was, to draw again over it
+        let under = match &placed {
+            Some((old, _)) if wanted != placed => {
+                let area = old.area().intersection(frame.buffer.area);
+                area.positions()
+                    .map(|at| (at.x, at.y, frame.buffer[at].clone()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        if wanted != placed {
+            if placed.take().is_some()
+                && graphics::clear(app.view_opts.graphics, terminal.backend_mut())?
+            {
+                // the old image is only gone once the cells under it are drawn again
+                let backend = terminal.backend_mut();
+                backend.draw(under.iter().map(|(x, y, cell)| (*x, *y, cell)))?;
+                Backend::flush(backend)?;
+            }
+            if let Some((placement, _)) = &wanted {
+                placement.place(terminal.backend_mut())?;
+            }
+            placed = wanted;
+        }
 
         if !event::poll(Duration::from_millis(if ui.active { 5 } else { 90 }))? {
             continue;
@@ -92,7 +192,76 @@ pub fn run(
             _ => None,
         };
 
-        if ui.bad_git_log.focus && ![Action::Abort].map(|v| Some(v)).contains(&binding_action) {
+        let passes_preview_focus = match &binding_action {
+            Some(
+                Action::FocusPreview
+                | Action::CopyPreviewLines
+                | Action::CopyKeyPath
+                | Action::ToggleFold
+                | Action::EditAtLine,
+            ) => true,
+            Some(Action::Abort) => !is_key(&ev, KeyCode::Esc),
+            _ => false,
+        };
+        if ui.preview_focus.focus && !passes_preview_focus {
+            let Event::Key(key) = ev else {
+                continue;
+            };
+            let mode = app.view_opts.preview_mode();
+            // less the search line
+            let height = usize::from(next_screen.side_pane.height.saturating_sub(1));
+            let extend = key.modifiers.contains(KeyModifiers::SHIFT);
+            let page = isize::try_from(height).unwrap_or(isize::MAX);
+            match key.code {
+                KeyCode::Esc => {
+                    ui.preview_focus.focus = false;
+                    ui.preview_focus.input.reset();
+                    ui.preview_focus.cursor = None;
+                    ui.preview_focus.anchor = None;
+                    continue;
+                }
+                KeyCode::Enter => {
+                    // `:120` and `50%` go there, rather than picking the line
+                    if ui_state::jump_preview(&mut ui, mode) {
+                        ui.preview_focus.input.reset();
+                        continue;
+                    }
+                    binding_action = Some(Action::AcceptPreviewLine);
+                }
+                KeyCode::Up => ui_state::move_preview_line(&mut ui, mode, -1, extend, height),
+                KeyCode::Down => ui_state::move_preview_line(&mut ui, mode, 1, extend, height),
+                KeyCode::PageUp => {
+                    ui_state::move_preview_line(&mut ui, mode, -page, extend, height)
+                }
+                KeyCode::PageDown => {
+                    ui_state::move_preview_line(&mut ui, mode, page, extend, height)
+                }
+                KeyCode::Tab => ui_state::jump_to_hit(&mut ui, mode, true),
+                KeyCode::BackTab => ui_state::jump_to_hit(&mut ui, mode, false),
+                _ => {
+                    if let Some(req) = to_input_request(&ev) {
+                        if ui
+                            .preview_focus
+                            .input
+                            .handle(req)
+                            .map(|change_of| change_of.value)
+                            .unwrap_or_default()
+                        {
+                            // search as you type, from the top
+                            ui.preview_focus.cursor = None;
+                            ui.preview_cursor = 0;
+                            ui_state::jump_to_hit(&mut ui, mode, true);
+                        }
+                    }
+                }
+            }
+
+            if binding_action != Some(Action::AcceptPreviewLine) {
+                continue;
+            }
+        }
+
+        if ui.bad_git_log.focus && ![Action::Abort].map(Some).contains(&binding_action) {
             match ev {
                 Event::Key(key) if key.code == KeyCode::Enter => {
                     let mut borrow = ui.bad_git_log.cache.borrow_mut();
@@ -128,15 +297,27 @@ pub fn run(
             continue;
         }
 
+        if let Some(cleanup) = ui.cleanup.as_mut().filter(|c| c.confirming) {
+            let Event::Key(key) = ev else {
+                continue;
+            };
+            cleanup.confirming = false;
+            binding_action = match key.code {
+                KeyCode::Char('t') => Some(Action::Dispose(Disposal::Trash)),
+                KeyCode::Char('D') => Some(Action::Dispose(Disposal::Delete)),
+                _ => continue,
+            };
+        }
+
         if ui.command_palette.showing
             && ![Action::CyclePalette, Action::Abort]
-                .map(|v| Some(v))
+                .map(Some)
                 .contains(&binding_action)
         {
-            let matches = filter_bindings(&app.bindings, &ui.command_palette.input.value());
+            let matches = filter_bindings(&app.bindings, ui.command_palette.input.value());
             match ev {
                 Event::Key(key) if key.code == KeyCode::Enter => {
-                    if let Some((_, _, action)) = matches.iter().nth(ui.command_palette.selected) {
+                    if let Some((_, _, action)) = matches.get(ui.command_palette.selected) {
                         binding_action = Some(action.clone());
                     }
                     ui.command_palette.showing = false;
@@ -185,10 +366,18 @@ pub fn run(
                 let action = handle_action(action, app, &mut ui)?;
                 match action {
                     ActionResult::Ignored => (),
+                    ActionResult::Repaint => {
+                        if placed.take().is_some() {
+                            graphics::clear(app.view_opts.graphics, terminal.backend_mut())?;
+                        }
+                        terminal.clear()?;
+                    }
                     ActionResult::Configured => {
                         let items_required = next_screen.items_required(&app.view_opts);
                         revalidate_cursor(&mut ui, snap, items_required);
                         ui.preview_cursor = 0;
+                        ui.preview_focus.cursor = None;
+                        ui.preview_focus.anchor = None;
 
                         ui_state::trigger_right_pane(&mut ui, app.view_opts, next_screen.side_pane);
                         reparse(store, &ui);
@@ -196,14 +385,25 @@ pub fn run(
 
                     ActionResult::Navigated => {
                         app.read_opts.expansions.clear();
+                        // going anywhere means browsing there, not the whole index
+                        app.read_opts.global = false;
                         reparse(store, &ui);
                         ui.prompt = format!("{}> ", app.here.display());
                         ui.sorted_items.clear();
                         ui.git_info = app.git_info();
+                        ui.ignores = Ignores::default();
+                        if ui.disk_usage.is_some() {
+                            ui.disk_usage = Some(DiskUsage::new(&app.here));
+                        }
+                        if let Some(cleanup) = &mut ui.cleanup {
+                            cleanup.refresh(&app.here);
+                        }
+                        app.read_opts.grep = ui.grep_query();
                         store.start_scan(app)?;
                     }
 
                     ActionResult::JustRescan => {
+                        reparse(store, &ui);
                         ui.sorted_items.clear();
                         store.start_scan(app)?;
                     }
@@ -215,7 +415,14 @@ pub fn run(
                 if let Some(req) = to_input_request(&ev) {
                     if ui.input.handle(req).map(|v| v.value).unwrap_or_default() {
                         ui.cursor_showing = None;
-                        reparse(store, &ui);
+                        if ui.grep {
+                            // the scan is the search, and restarting it cancels the last one
+                            app.read_opts.grep = ui.grep_query();
+                            ui.sorted_items.clear();
+                            store.start_scan(app)?;
+                        } else {
+                            reparse(store, &ui);
+                        }
                     }
                 }
             }
@@ -223,10 +430,14 @@ pub fn run(
     }
 }
 
+fn is_key(ev: &Event, code: KeyCode) -> bool {
+    matches!(ev, Event::Key(key) if key.code == code)
+}
+
 fn reparse(store: &mut Store, ui: &Ui) {
     store.nucleo.pattern.reparse(
         0,
-        ui.input.value(),
+        if ui.grep { "" } else { ui.input.value() },
         CaseMatching::Smart,
         Normalization::Smart,
         false,
@@ -1,44 +1,88 @@
+use crate::cleanup::item_rank;
+use crate::du::DiskUsage;
 use crate::item::Item;
-use crate::ui_state::{SortedItems, Ui};
+use crate::photo::CaptureDates;
+use crate::ui_state::{SortOrder, SortedItems, Ui};
 use nucleo::Snapshot;
+use std::cmp::{Ordering, Reverse};
+use std::time::SystemTime;
 
 pub fn ui_item_range<'s>(ui: &mut Ui, snap: &'s Snapshot<Item>, len: u32) -> Snapped<'s> {
-    item_range(
-        snap,
-        ui.view_start,
-        len,
-        should_sort(ui),
-        &mut ui.sorted_items,
-    )
+    ui_range(ui, snap, ui.view_start, len)
 }
 
 fn one_item<'s>(idx: u32, ui: &mut Ui, snap: &'s Snapshot<Item>) -> Option<&'s Item> {
-    item_range(snap, idx, 1, should_sort(ui), &mut ui.sorted_items)
-        .items
-        .pop()
+    ui_range(ui, snap, idx, 1).items.pop()
+}
+
+fn ui_range<'s>(ui: &mut Ui, snap: &'s Snapshot<Item>, start: u32, len: u32) -> Snapped<'s> {
+    let sort = should_sort(ui).then_some(Sorter {
+        order: ui.sort,
+        disk_usage: ui.disk_usage.as_ref(),
+        capture_dates: &ui.capture_dates,
+        now: SystemTime::now(),
+    });
+    item_range(snap, start, len, sort, &mut ui.sorted_items)
 }
 
 fn should_sort(ui: &Ui) -> bool {
-    ui.input.value().is_empty()
+    !ui.is_searching()
+}
+
+#[derive(Copy, Clone)]
+struct Sorter<'u> {
+    order: SortOrder,
+    disk_usage: Option<&'u DiskUsage>,
+    capture_dates: &'u CaptureDates,
+    /// ages are measured from here, so they don't move during a sort
+    now: SystemTime,
+}
+
+/// What an item sorts by, taken once per sort: sizes are still being added up while
+/// it runs, and a comparison which changes its mind part way through can panic it.
+enum Key {
+    Size(Option<u64>),
+    Stale(f64),
+    Taken(Option<u64>),
+    Name,
+}
+
+impl Sorter<'_> {
+    fn key(&self, item: &Item) -> Key {
+        match (self.order, self.disk_usage) {
+            (SortOrder::Size, Some(du)) => Key::Size(du.size(item).map(|s| s.bytes)),
+            (SortOrder::Stale, du) => Key::Stale(item_rank(item, du, self.now)),
+            (SortOrder::Taken, _) => {
+                Key::Taken(item.path().and_then(|p| self.capture_dates.taken(p)))
+            }
+            _ => Key::Name,
+        }
+    }
+}
+
+impl Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            (Key::Size(a), Key::Size(b)) => Reverse(a).cmp(&Reverse(b)),
+            (Key::Stale(a), Key::Stale(b)) => b.total_cmp(a),
+            // undated last
+            (Key::Taken(a), Key::Taken(b)) => (a.is_none(), a).cmp(&(b.is_none(), b)),
+            _ => Ordering::Equal,
+        }
+    }
 }
 
 pub fn revalidate_cursor(ui: &mut Ui, snap: &Snapshot<Item>, len: u32) {
     let mut pos = match one_item(ui.cursor.last_pos, ui, snap) {
         Some(item) if Some(item) == ui.cursor_showing.as_ref() => ui.cursor.last_pos,
 
-        _ => item_range(
-            snap,
-            0,
-            ui.sorted_items.until.saturating_add(64),
-            should_sort(ui),
-            &mut ui.sorted_items,
-        )
-        .items
-        .into_iter()
-        .position(|item| Some(item) == ui.cursor_showing.as_ref())
-        .and_then(|i| u32::try_from(i).ok())
-        // if it's gone, jump to the start
-        .unwrap_or(0),
+        _ => ui_range(ui, snap, 0, ui.sorted_items.until.saturating_add(64))
+            .items
+            .into_iter()
+            .position(|item| Some(item) == ui.cursor_showing.as_ref())
+            .and_then(|i| u32::try_from(i).ok())
+            // if it's gone, jump to the start
+            .unwrap_or(0),
     };
 
     let list_end = snap.matched_item_count().saturating_sub(1);
@@ -50,10 +94,7 @@ pub fn revalidate_cursor(ui: &mut Ui, snap: &Snapshot<Item>, len: u32) {
 
     ui.cursor.last_pos = pos;
 
-    ui.cursor_showing = item_range(snap, pos, 1, should_sort(ui), &mut ui.sorted_items)
-        .items
-        .pop()
-        .cloned();
+    ui.cursor_showing = ui_range(ui, snap, pos, 1).items.pop().cloned();
 
     if pos < ui.view_start {
         ui.view_start = pos;
@@ -73,7 +114,7 @@ fn item_range<'s>(
     snap: &'s Snapshot<Item>,
     start: u32,
     len: u32,
-    sort: bool,
+    sort: Option<Sorter>,
     sorted_items: &mut SortedItems,
 ) -> Snapped<'s> {
     let mut end = start.saturating_add(len);
@@ -89,12 +130,12 @@ fn item_range<'s>(
         };
     }
 
-    let items = if !sort {
-        snap.matched_items(start..end)
+    let items = match sort {
+        None => snap
+            .matched_items(start..end)
             .map(|item| item.data)
-            .collect()
-    } else {
-        item_range_sorted(snap, start, end, sorted_items)
+            .collect(),
+        Some(sorter) => item_range_sorted(snap, start, end, sorter, sorted_items),
     };
 
     Snapped {
@@ -109,12 +150,15 @@ fn item_range_sorted<'s>(
     snap: &'s Snapshot<Item>,
     start: u32,
     end: u32,
+    sorter: Sorter,
     sorted_items: &mut SortedItems,
 ) -> Vec<&'s Item> {
+    let item = |i: u32| snap.get_item(i).expect("<end").data;
+
     let real_end = snap.matched_item_count();
     let cache_end = sorted_items.items.len() as u32;
     let could_extend = real_end > cache_end;
-    let should_extend = end * 2 > cache_end || real_end % 64 == 0;
+    let should_extend = end * 2 > cache_end || real_end.is_multiple_of(64);
     let should_sort = end > sorted_items.until;
 
     if should_sort || (could_extend && should_extend) {
@@ -122,21 +166,26 @@ fn item_range_sorted<'s>(
 
         let target_until = end.min(100_000);
 
+        let mut keyed: Vec<(Key, u32)> = sorted_items
+            .items
+            .iter()
+            .map(|&i| (sorter.key(item(i)), i))
+            .collect();
+        let cmp = |(key_a, a): &(Key, u32), (key_b, b): &(Key, u32)| {
+            key_a.cmp(key_b).then_with(|| item(*a).cmp(item(*b)))
+        };
+
         if target_until < real_end {
-            sorted_items
-                .items
-                .select_nth_unstable_by_key(target_until as usize, |&i| {
-                    snap.get_item(i).expect("<end").data
-                });
+            keyed.select_nth_unstable_by(target_until as usize, cmp);
         }
 
-        sorted_items.items[0..target_until as usize]
-            .sort_unstable_by_key(|&i| snap.get_item(i).expect("<end").data);
+        keyed[0..target_until as usize].sort_unstable_by(cmp);
+        sorted_items.items = keyed.into_iter().map(|(_, i)| i).collect();
         sorted_items.until = target_until;
     }
 
     sorted_items.items[start as usize..end as usize]
         .iter()
-        .map(|&i| snap.get_item(i).expect("<end").data)
+        .map(|&i| item(i))
         .collect()
 }
@@ -5,14 +5,18 @@ use std::time::{Duration, Instant};
 
 use crate::fuzz::AddItem;
 use crate::item::Item;
-use crate::walk::stream_content;
+use crate::walk::{stream_content, Source};
+use crate::watch::{Changes, Watch};
 use crate::App;
 use anyhow::Result;
+use log::info;
 use nucleo::Nucleo;
 
 pub struct Store {
     pub nucleo: Nucleo<Item>,
     current_scan: Option<CurrentScan>,
+    watch: Option<Watch>,
+    watch_failed: bool,
 }
 
 pub struct CurrentScan {
@@ -26,6 +30,8 @@ impl Store {
         Self {
             nucleo,
             current_scan: None,
+            watch: None,
+            watch_failed: false,
         }
     }
 
@@ -39,6 +45,10 @@ impl Store {
             cancelled: cancellation.clone(),
         };
 
+        // the listing is about to change under the watch, so it restarts after the scan
+        self.watch = None;
+        self.watch_failed = false;
+
         let here = app.here.to_path_buf();
         let read_opts = app.read_opts.clone();
 
@@ -69,6 +79,78 @@ impl Store {
             .unwrap_or(false)
     }
 
+    /// Apply filesystem changes seen since the last call, once the scan is complete.
+    /// @return the changes, and whether existing item indices were invalidated
+    pub fn poll_watch(&mut self, app: &App) -> Result<(Changes, bool)> {
+        if !app.read_opts.watch
+            || app.read_opts.source != Source::Walk
+            || app.read_opts.grep.is_some()
+            || app.read_opts.global
+        {
+            self.watch = None;
+            return Ok((Changes::default(), false));
+        }
+
+        if self.is_scanning() || self.watch_failed {
+            return Ok((Changes::default(), false));
+        }
+
+        let watch = match &mut self.watch {
+            Some(watch) => watch,
+            None => {
+                let item_count = self.nucleo.snapshot().item_count();
+                match Watch::new(&app.here, &app.read_opts, item_count) {
+                    Ok(watch) => self.watch.insert(watch),
+                    Err(e) => {
+                        info!("watch: couldn't watch {:?}: {e}", app.here);
+                        self.watch_failed = true;
+                        return Ok((Changes::default(), false));
+                    }
+                }
+            }
+        };
+
+        let changes = watch.drain();
+        let fresh = watch.listed_items(&app.here, &app.read_opts, &changes);
+        if fresh.is_empty() && changes.upserted.is_empty() && changes.removed.is_empty() {
+            return Ok((changes, false));
+        }
+
+        let stale = |item: &Item| {
+            item.path()
+                .map(|p| {
+                    changes.upserted.contains(p) || changes.removed.iter().any(|r| p.starts_with(r))
+                })
+                .unwrap_or_default()
+        };
+
+        let snap = self.nucleo.snapshot();
+        let all_items = || (0..snap.item_count()).filter_map(|i| snap.get_item(i));
+        let restart = all_items().any(|item| stale(item.data));
+
+        // nucleo can only append, so removals mean starting over with what's left
+        let kept = if restart {
+            all_items()
+                .map(|item| item.data)
+                .filter(|item| !stale(item))
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        if restart {
+            self.nucleo.restart(true);
+        }
+
+        let tx = AddItem::new(self.nucleo.injector());
+        for item in kept.into_iter().chain(fresh) {
+            let _ = tx.send(item);
+        }
+
+        Ok((changes, restart))
+    }
+
     pub fn cancel_scan(&mut self) -> Result<()> {
         if let Some(scan) = self.current_scan.take() {
             scan.cancellation
@@ -0,0 +1,208 @@
+use std::path::Path;
+
+use ratatui::prelude::*;
+
+use crate::preview::preview_header;
+use crate::ui_state::URect;
+
+/// The preview's header, the table's header, and the rule under it stay put while scrolling.
+pub const PINNED: usize = 3;
+
+const DELIMITERS: [u8; 4] = *b",\t;|";
+
+/// narrower than this, and there's no telling what's in a column
+const NARROWEST: usize = 3;
+
+const SEPARATOR: &str = " │ ";
+
+pub fn is_table(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["csv", "tsv", "tab", "psv"]
+                .iter()
+                .any(|t| ext.eq_ignore_ascii_case(t))
+        })
+}
+
+/// The rows as a table, fitted to the area, with the first row as the header.
+/// `truncated` says that `content` is only the start of the file, so there are more rows.
+pub fn render(
+    content: &[u8],
+    showing: &Path,
+    area: URect,
+    coloured: bool,
+    truncated: bool,
+) -> Text<'static> {
+    let delimiter = delimiter(showing, content);
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content);
+
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut count = 0usize;
+    let mut columns = 0;
+    for record in reader.byte_records() {
+        // probably the record the end of the window cut in half
+        let Ok(record) = record else {
+            break;
+        };
+        count += 1;
+        columns = columns.max(record.len());
+        if rows.len() < area.height {
+            rows.push(
+                record
+                    .iter()
+                    .map(|field| String::from_utf8_lossy(field).replace(['\t', '\n', '\r'], " "))
+                    .collect(),
+            );
+        }
+    }
+
+    // less the header
+    let body = count.saturating_sub(1);
+    let mut header = preview_header(if delimiter == b'\t' { "tsv" } else { "csv" }, showing);
+    header.spans.push(Span::styled(
+        format!(
+            "  {body}{} {} × {columns} {}",
+            if truncated { "+" } else { "" },
+            if body == 1 { "row" } else { "rows" },
+            if columns == 1 { "column" } else { "columns" },
+        ),
+        Style::new().dark_gray(),
+    ));
+    let mut lines = vec![header];
+
+    let widths = fit(&rows, columns, area.width);
+    let numeric: Vec<bool> = (0..columns)
+        .map(|c| {
+            let mut cells = rows.iter().skip(1).filter_map(|row| row.get(c)).peekable();
+            cells.peek().is_some()
+                && cells.all(|cell| cell.is_empty() || cell.trim().parse::<f64>().is_ok())
+        })
+        .collect();
+    let border = if coloured {
+        Style::new().dark_gray()
+    } else {
+        Style::new()
+    };
+
+    for (r, row) in rows.iter().enumerate() {
+        let mut spans = Vec::with_capacity(columns * 2);
+        for (c, &width) in widths.iter().enumerate() {
+            if c > 0 {
+                spans.push(Span::styled(SEPARATOR, border));
+            }
+            let cell = truncate(row.get(c).map_or("", String::as_str), width);
+            let pad = " ".repeat(width.saturating_sub(Span::raw(cell.as_str()).width()));
+            let cell = if numeric[c] && r > 0 {
+                format!("{pad}{cell}")
+            } else {
+                format!("{cell}{pad}")
+            };
+            spans.push(if r == 0 {
+                Span::styled(cell, Style::new().bold())
+            } else {
+                Span::raw(cell)
+            });
+        }
+        lines.push(Line::from(spans));
+        if r == 0 {
+            let rule = widths
+                .iter()
+                .map(|&w| "─".repeat(w))
+                .collect::<Vec<_>>()
+                .join("─┼─");
+            lines.push(Line::styled(rule, border));
+        }
+    }
+    Text::from(lines)
+}
+
+/// The delimiter which splits the first few lines into the same number of fields, the most.
+fn delimiter(path: &Path, content: &[u8]) -> u8 {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("tsv") || ext.eq_ignore_ascii_case("tab"))
+    {
+        return b'\t';
+    }
+    let lines: Vec<&[u8]> = content
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .take(20)
+        .collect();
+    DELIMITERS
+        .into_iter()
+        .map(|d| {
+            let counts: Vec<usize> = lines
+                .iter()
+                .map(|line| count_outside_quotes(line, d))
+                .collect();
+            let least = counts.iter().copied().min().unwrap_or(0);
+            let consistent = least > 0 && counts.iter().all(|&n| n == least);
+            (d, (consistent, least))
+        })
+        .filter(|(_, (_, least))| *least > 0)
+        .max_by_key(|(_, score)| *score)
+        .map_or(b',', |(d, _)| d)
+}
+
+fn count_outside_quotes(line: &[u8], delimiter: u8) -> usize {
+    let mut quoted = false;
+    line.iter()
+        .filter(|&&b| {
+            if b == b'"' {
+                quoted = !quoted;
+            }
+            b == delimiter && !quoted
+        })
+        .count()
+}
+
+/// Column widths: as wide as they need to be, unless that's too wide for the area,
+/// in which case the widest are cut down to share what's left.
+fn fit(rows: &[Vec<String>], columns: usize, width: usize) -> Vec<usize> {
+    let natural: Vec<usize> = (0..columns)
+        .map(|c| {
+            rows.iter()
+                .filter_map(|row| row.get(c))
+                .map(|cell| Span::raw(cell.as_str()).width())
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        })
+        .collect();
+    let separators = Span::raw(SEPARATOR).width() * columns.saturating_sub(1);
+    let available = width.saturating_sub(separators);
+    if natural.iter().sum::<usize>() <= available {
+        return natural;
+    }
+    // the widest cap which fits; with too many columns, even the narrowest don't
+    let mut cap = natural.iter().copied().max().unwrap_or(0);
+    while cap > NARROWEST && natural.iter().map(|&w| w.min(cap)).sum::<usize>() > available {
+        cap -= 1;
+    }
+    natural.iter().map(|&w| w.min(cap)).collect()
+}
+
+/// Cut to fit the width, with an ellipsis to show it's been cut.
+fn truncate(cell: &str, width: usize) -> String {
+    if Span::raw(cell).width() <= width {
+        return cell.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in cell.chars() {
+        let w = Span::raw(c.to_string()).width();
+        if used + w + 1 > width {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out.push('…');
+    out
+}
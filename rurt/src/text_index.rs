@@ -0,0 +1,436 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::persist::{
+    index_file, read_path, read_u32, read_u64, take, write_atomically, write_path,
+};
+use anyhow::{bail, Result};
+use ignore::WalkBuilder;
+use log::info;
+use regex_syntax::hir::literal::Extractor;
+
+const MAGIC: &[u8] = b"rurtidx1";
+
+/// Files bigger than this aren't indexed, and are searched whenever they might match
+const MAX_INDEXED: u64 = 8 * 1024 * 1024;
+
+const REFRESH_EVERY: Duration = Duration::from_secs(60);
+
+/// On-disk trigram indexes of the text files under some roots, kept up to date
+/// in the background. They only narrow down which files need searching; the
+/// grep still has the final say.
+pub struct TextIndex {
+    roots: Vec<Arc<RootIndex>>,
+}
+
+struct RootIndex {
+    root: PathBuf,
+    /// None until it's been loaded or built
+    trigrams: RwLock<Option<Trigrams>>,
+}
+
+#[derive(Default)]
+struct Trigrams {
+    files: Vec<FileRecord>,
+    by_path: HashMap<PathBuf, u32>,
+    /// trigram -> ids of files containing it, ascending
+    postings: HashMap<u32, Vec<u32>>,
+}
+
+#[derive(Clone)]
+struct FileRecord {
+    /// relative to the root
+    path: PathBuf,
+    stamp: (u64, u32, u64),
+    live: bool,
+    /// false for files too big to index, which are always candidates
+    indexed: bool,
+}
+
+impl TextIndex {
+    pub fn start(roots: Vec<PathBuf>) -> Arc<Self> {
+        let roots = roots
+            .into_iter()
+            .map(|root| {
+                let root = Arc::new(RootIndex {
+                    root,
+                    trigrams: RwLock::new(None),
+                });
+                let maintained = Arc::clone(&root);
+                thread::spawn(move || maintained.maintain());
+                root
+            })
+            .collect();
+        Arc::new(Self { roots })
+    }
+
+    /// Files under `src` which might contain a match for `query`.
+    /// None if no index covers `src` yet.
+    pub fn candidates(&self, src: &Path, query: &str) -> Option<Vec<PathBuf>> {
+        self.roots
+            .iter()
+            .find(|r| src.starts_with(&r.root))?
+            .candidates(src, query)
+    }
+}
+
+impl RootIndex {
+    fn maintain(&self) {
+        let store = index_file(&self.root, "idx");
+        if let Some(store) = &store {
+            match load(store) {
+                Ok(loaded) => {
+                    info!(
+                        "index: loaded {} files for {:?}",
+                        loaded.files.len(),
+                        self.root
+                    );
+                    *self.trigrams.write().expect("panic") = Some(loaded);
+                }
+                Err(e) => info!("index: not loading {store:?}: {e}"),
+            }
+        }
+
+        loop {
+            match self.refresh() {
+                Ok(0) => (),
+                Ok(changed) => {
+                    info!("index: {changed} changes under {:?}", self.root);
+                    if let Some(store) = &store {
+                        let trigrams = self.trigrams.read().expect("panic");
+                        if let Err(e) = save(store, trigrams.as_ref().expect("refreshed")) {
+                            info!("index: couldn't save {store:?}: {e}");
+                        }
+                    }
+                }
+                Err(e) => info!("index: refreshing {:?}: {e}", self.root),
+            }
+            thread::sleep(REFRESH_EVERY);
+        }
+    }
+
+    /// Bring the index in line with the disk, reading only what's changed.
+    /// @return how many files were added, changed or removed
+    fn refresh(&self) -> Result<usize> {
+        let seen = self.on_disk(&self.root);
+
+        // work out what to read without holding up searches
+        let (stale, removed) = {
+            let trigrams = self.trigrams.read().expect("panic");
+            let empty = Trigrams::default();
+            let current = trigrams.as_ref().unwrap_or(&empty);
+            let seen_paths = seen.iter().map(|(p, _)| p).collect::<HashSet<_>>();
+            let stale = seen
+                .iter()
+                .filter(|(path, stamp)| {
+                    current
+                        .by_path
+                        .get(path)
+                        .is_none_or(|&id| current.files[id as usize].stamp != *stamp)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let removed = current
+                .by_path
+                .keys()
+                .filter(|p| !seen_paths.contains(p))
+                .cloned()
+                .collect::<Vec<_>>();
+            (stale, removed)
+        };
+
+        let read = stale
+            .into_iter()
+            .map(|(path, stamp)| {
+                let found = file_trigrams(&self.root.join(&path), stamp.2);
+                (path, stamp, found)
+            })
+            .collect::<Vec<_>>();
+
+        let changed = read.len() + removed.len();
+        let mut trigrams = self.trigrams.write().expect("panic");
+        let trigrams = trigrams.get_or_insert_with(Trigrams::default);
+        for path in removed {
+            trigrams.remove(&path);
+        }
+        for (path, stamp, found) in read {
+            trigrams.remove(&path);
+            trigrams.insert(path, stamp, found);
+        }
+        if changed > 0 {
+            trigrams.compact();
+        }
+        Ok(changed)
+    }
+
+    /// The files under `dir` as they are now, relative to the root, with their stamps.
+    fn on_disk(&self, dir: &Path) -> Vec<(PathBuf, (u64, u32, u64))> {
+        let mut found = Vec::new();
+        for entry in WalkBuilder::new(dir).follow_links(true).build() {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let Ok(rel) = entry.path().strip_prefix(&self.root) else {
+                continue;
+            };
+            found.push((rel.to_path_buf(), stamp(&metadata)));
+        }
+        found
+    }
+
+    /// The index only rules out files it's read as they are now; anything created or
+    /// changed since the last refresh is a candidate regardless.
+    fn candidates(&self, src: &Path, query: &str) -> Option<Vec<PathBuf>> {
+        src.strip_prefix(&self.root).ok()?;
+        if self.trigrams.read().ok()?.is_none() {
+            return None;
+        }
+        // stat everything before taking the lock, so refreshes aren't held up
+        let on_disk = self.on_disk(src);
+
+        let trigrams = self.trigrams.read().ok()?;
+        let trigrams = trigrams.as_ref()?;
+
+        let matched = match required_trigrams(query) {
+            None => None,
+            Some(alternatives) => {
+                let mut ids = alternatives
+                    .iter()
+                    .flat_map(|all_of| trigrams.containing_all(all_of))
+                    .collect::<Vec<_>>();
+                ids.sort_unstable();
+                ids.dedup();
+                Some(ids)
+            }
+        };
+
+        Some(
+            on_disk
+                .into_iter()
+                .filter(|(path, stamp)| match trigrams.by_path.get(path) {
+                    Some(&id) if trigrams.files[id as usize].stamp == *stamp => {
+                        !trigrams.files[id as usize].indexed
+                            || matched
+                                .as_ref()
+                                .is_none_or(|ids| ids.binary_search(&id).is_ok())
+                    }
+                    _ => true,
+                })
+                .map(|(path, _)| self.root.join(path))
+                .collect(),
+        )
+    }
+}
+
+impl Trigrams {
+    fn remove(&mut self, path: &Path) {
+        // postings are cleaned up when compacting
+        if let Some(id) = self.by_path.remove(path) {
+            self.files[id as usize].live = false;
+        }
+    }
+
+    fn insert(&mut self, path: PathBuf, stamp: (u64, u32, u64), found: Found) {
+        let id = self.files.len() as u32;
+        let indexed = !matches!(found, Found::TooBig);
+        if let Found::Text(set) = found {
+            for trigram in set {
+                self.postings.entry(trigram).or_default().push(id);
+            }
+        }
+        self.by_path.insert(path.clone(), id);
+        self.files.push(FileRecord {
+            path,
+            stamp,
+            live: true,
+            indexed,
+        });
+    }
+
+    /// Drop removed files, and renumber what's left.
+    fn compact(&mut self) {
+        let mut remap = vec![None; self.files.len()];
+        let mut files = Vec::with_capacity(self.by_path.len());
+        for (old, file) in self.files.drain(..).enumerate() {
+            if file.live {
+                remap[old] = Some(files.len() as u32);
+                files.push(file);
+            }
+        }
+        self.files = files;
+        self.by_path = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(id, file)| (file.path.clone(), id as u32))
+            .collect();
+        for ids in self.postings.values_mut() {
+            *ids = ids.iter().filter_map(|&id| remap[id as usize]).collect();
+        }
+        self.postings.retain(|_, ids| !ids.is_empty());
+    }
+
+    fn containing_all(&self, all_of: &[u32]) -> Vec<u32> {
+        let mut lists = Vec::with_capacity(all_of.len());
+        for trigram in all_of {
+            match self.postings.get(trigram) {
+                Some(ids) => lists.push(ids),
+                None => return Vec::new(),
+            }
+        }
+        lists.sort_by_key(|ids| ids.len());
+        let Some((shortest, rest)) = lists.split_first() else {
+            return Vec::new();
+        };
+        shortest
+            .iter()
+            .copied()
+            .filter(|id| rest.iter().all(|ids| ids.binary_search(id).is_ok()))
+            .collect()
+    }
+}
+
+enum Found {
+    Text(HashSet<u32>),
+    /// or unreadable; either way, never a match
+    Binary,
+    TooBig,
+}
+
+fn file_trigrams(path: &Path, len: u64) -> Found {
+    if len > MAX_INDEXED {
+        return Found::TooBig;
+    }
+    let Ok(content) = fs::read(path) else {
+        return Found::Binary;
+    };
+    // the same check as the preview
+    if content_inspector::inspect(&content).is_binary() {
+        return Found::Binary;
+    }
+    Found::Text(content.windows(3).map(trigram).collect())
+}
+
+/// case folded, so one index serves both smart case searches
+fn trigram(bytes: &[u8]) -> u32 {
+    let b = |i: usize| u32::from(bytes[i].to_ascii_lowercase());
+    (b(0) << 16) | (b(1) << 8) | b(2)
+}
+
+/// For each way the query could match, the trigrams a file must contain.
+/// None if the query can't be narrowed down, e.g. `.*` or `ab`.
+fn required_trigrams(query: &str) -> Option<Vec<Vec<u32>>> {
+    // the grep takes invalid patterns literally, so we do too
+    let literals = match regex_syntax::parse(query) {
+        Ok(hir) => {
+            let seq = Extractor::new().extract(&hir);
+            seq.literals()?
+                .iter()
+                .map(|lit| lit.as_bytes().to_vec())
+                .collect()
+        }
+        Err(_) => vec![query.as_bytes().to_vec()],
+    };
+    if literals.is_empty() {
+        return None;
+    }
+    literals
+        .iter()
+        .map(|lit| {
+            // non-ascii case folding doesn't survive our lowercasing
+            let mut all_of = lit
+                .windows(3)
+                .filter(|w| w.is_ascii())
+                .map(trigram)
+                .collect::<Vec<_>>();
+            all_of.sort_unstable();
+            all_of.dedup();
+            (!all_of.is_empty()).then_some(all_of)
+        })
+        .collect()
+}
+
+fn stamp(metadata: &fs::Metadata) -> (u64, u32, u64) {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    (modified.as_secs(), modified.subsec_nanos(), metadata.len())
+}
+
+fn save(store: &Path, trigrams: &Trigrams) -> Result<()> {
+    write_atomically(store, |out| {
+        out.write_all(MAGIC)?;
+        out.write_all(&(trigrams.files.len() as u32).to_le_bytes())?;
+        for file in &trigrams.files {
+            write_path(out, &file.path)?;
+            out.write_all(&file.stamp.0.to_le_bytes())?;
+            out.write_all(&file.stamp.1.to_le_bytes())?;
+            out.write_all(&file.stamp.2.to_le_bytes())?;
+            out.write_all(&[u8::from(file.indexed)])?;
+        }
+        out.write_all(&(trigrams.postings.len() as u32).to_le_bytes())?;
+        for (trigram, ids) in &trigrams.postings {
+            out.write_all(&trigram.to_le_bytes())?;
+            out.write_all(&(ids.len() as u32).to_le_bytes())?;
+            for id in ids {
+                out.write_all(&id.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    })
+}
+
+fn load(store: &Path) -> Result<Trigrams> {
+    let data = fs::read(store)?;
+    let mut buf = data.as_slice();
+    if take(&mut buf, MAGIC.len())? != MAGIC {
+        bail!("not an index, or an old one");
+    }
+
+    let mut trigrams = Trigrams::default();
+    for id in 0..read_u32(&mut buf)? {
+        let path = read_path(&mut buf)?;
+        let stamp = (
+            read_u64(&mut buf)?,
+            read_u32(&mut buf)?,
+            read_u64(&mut buf)?,
+        );
+        let indexed = take(&mut buf, 1)?[0] != 0;
+        trigrams.by_path.insert(path.clone(), id);
+        trigrams.files.push(FileRecord {
+            path,
+            stamp,
+            live: true,
+            indexed,
+        });
+    }
+    for _ in 0..read_u32(&mut buf)? {
+        let trigram = read_u32(&mut buf)?;
+        let ids = (0..read_u32(&mut buf)?)
+            .map(|_| read_u32(&mut buf))
+            .collect::<Result<Vec<_>>>()?;
+        // searches index files by these, and intersect them assuming they're in order
+        if ids
+            .last()
+            .is_some_and(|&id| id as usize >= trigrams.files.len())
+            || !ids.windows(2).all(|pair| pair[0] < pair[1])
+        {
+            bail!("corrupt postings for trigram {trigram:#x}");
+        }
+        trigrams.postings.insert(trigram, ids);
+    }
+    Ok(trigrams)
+}
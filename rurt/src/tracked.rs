@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::fuzz::AddItem;
+use crate::item::{FileKind, Item, ItemInfo};
+use crate::walk::{Mode, ReadOpts, Recursion, MODES};
+
+use anyhow::{anyhow, Result};
+use gix::bstr::ByteSlice;
+use gix::dir::entry::{Kind as DirKind, Status};
+use gix::dir::walk::EmissionMode;
+use gix::index::entry::Mode as IndexMode;
+
+/// List paths straight from the git index, without touching the worktree,
+/// optionally followed by the untracked (but not ignored) files.
+pub fn stream_tracked(
+    tx: AddItem,
+    src: impl AsRef<Path>,
+    read_opts: &ReadOpts,
+    untracked: bool,
+) -> Result<()> {
+    let src = src.as_ref();
+    let repo = gix::discover(src)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("no worktree to list"))?
+        .canonicalize()?;
+    let prefix = src.strip_prefix(&workdir)?.to_path_buf();
+
+    let mut emitter = Emitter::new(&tx, &workdir, &prefix, read_opts);
+
+    let index = repo.index_or_empty()?;
+    let mut last = None;
+    for entry in index.entries() {
+        // a conflicted path has an entry for each side, one after the other
+        let path = entry.path(&index);
+        if last == Some(path) {
+            continue;
+        }
+        last = Some(path);
+        let kind = match entry.mode {
+            IndexMode::SYMLINK => FileKind::Symlink,
+            IndexMode::DIR | IndexMode::COMMIT => FileKind::Dir,
+            _ => FileKind::File,
+        };
+        if emitter.emit(&gix::path::from_bstr(path), kind) {
+            return Ok(());
+        }
+    }
+
+    if !untracked {
+        return Ok(());
+    }
+
+    let options = repo
+        .dirwalk_options()?
+        .emit_untracked(EmissionMode::Matching)
+        .emit_ignored(None)
+        .emit_tracked(false);
+    let patterns = if prefix.as_os_str().is_empty() {
+        vec![]
+    } else {
+        vec![format!(":(top){}", prefix.display())]
+    };
+
+    for item in repo.dirwalk_iter(index, patterns, tx.cancelled.clone().into(), options)? {
+        let entry = item?.entry;
+        if entry.status != Status::Untracked {
+            continue;
+        }
+        let kind = match entry.disk_kind {
+            Some(DirKind::File) => FileKind::File,
+            Some(DirKind::Symlink) => FileKind::Symlink,
+            Some(DirKind::Directory | DirKind::Repository) => FileKind::Dir,
+            Some(DirKind::Untrackable) | None => FileKind::Other,
+        };
+        if emitter.emit(&gix::path::from_bstr(entry.rela_path.as_bstr()), kind) {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns a flat list of paths (relative to `root`) into a listing of `prefix`,
+/// making up any directories which aren't listed themselves.
+pub struct Emitter<'a> {
+    tx: &'a AddItem,
+    root: &'a Path,
+    prefix: &'a Path,
+    read_opts: &'a ReadOpts,
+    seen_dirs: HashSet<PathBuf>,
+}
+
+impl<'a> Emitter<'a> {
+    pub fn new(tx: &'a AddItem, root: &'a Path, prefix: &'a Path, read_opts: &'a ReadOpts) -> Self {
+        Self {
+            tx,
+            root,
+            prefix,
+            read_opts,
+            seen_dirs: HashSet::new(),
+        }
+    }
+
+    /* @return true if we should early exit */
+    pub fn emit(&mut self, rela: &Path, kind: FileKind) -> bool {
+        let name = match rela.strip_prefix(self.prefix) {
+            Ok(name) if !name.as_os_str().is_empty() => name,
+            _ => return false,
+        };
+
+        if !self.read_opts.show_hidden && name.components().any(is_hidden) {
+            return false;
+        }
+
+        let depth = name.components().count();
+        let recursive = self.read_opts.recursion != Recursion::None;
+
+        // the index only has files, so we make up the directories on the way
+        let parents = name
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect::<Vec<_>>();
+        for dir in parents.into_iter().rev() {
+            if !recursive && dir.components().count() > 1 {
+                break;
+            }
+            if self.seen_dirs.insert(dir.to_path_buf()) && self.send(dir, FileKind::Dir) {
+                return true;
+            }
+        }
+
+        if !recursive && depth > 1 {
+            return false;
+        }
+
+        if kind == FileKind::Dir && !self.seen_dirs.insert(name.to_path_buf()) {
+            return false;
+        }
+
+        self.send(name, kind)
+    }
+
+    fn send(&self, name: &Path, kind: FileKind) -> bool {
+        let wanted = match MODES[self.read_opts.mode_index] {
+            Mode::Mixed => true,
+            Mode::Files => kind != FileKind::Dir,
+            Mode::Dirs => kind == FileKind::Dir,
+        };
+        if !wanted {
+            return false;
+        }
+
+        let path = self.root.join(self.prefix).join(name);
+        let item = Item::FileEntry {
+            name: name.as_os_str().to_owned(),
+            info: Arc::new(ItemInfo::unstatted(path, kind)),
+        };
+        self.tx.send(item).is_err()
+    }
+}
+
+fn is_hidden(component: Component) -> bool {
+    component.as_os_str().as_encoded_bytes().starts_with(b".")
+}
@@ -1,17 +1,39 @@
+use crate::cleanup::Cleanup;
+use crate::dir_preview::Listing;
 use crate::draw::{PreviewMode, RightPane, ViewOpts};
+use crate::du::DiskUsage;
 use crate::git::Git;
 use crate::git_but_bad::{bad_log, LogData, Logs};
+use crate::graphics::Placement;
+use crate::grep::matcher;
+use crate::ignores::Ignores;
 use crate::item::Item;
-use crate::preview::{run_preview, Preview, PreviewedData, Previews};
+use crate::paging::{Page, Paging, Window};
+use crate::photo::CaptureDates;
+use crate::pool::panic_message;
+use crate::preview::{
+    run_preview, stamp, Preview, PreviewRequest, PreviewStyle, PreviewedData, Previews,
+};
+use anyhow::anyhow;
+use grep_matcher::Matcher;
+use grep_regex::RegexMatcher;
 use log::info;
 use lscolors::LsColors;
 use ratatui::layout::Rect;
-use std::path::Path;
+use ratatui::text::{Line, Text};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
-use std::thread;
 use std::time::Instant;
 use tui_input::Input;
 
+/// Lines to leave above a hit when jumping to it
+const PREVIEW_CONTEXT: usize = 2;
+
 pub struct Ui {
     pub boot: Instant,
     pub input: Input,
@@ -23,21 +45,108 @@ pub struct Ui {
     pub sorted_items: SortedItems,
     pub previews: Previews,
     pub git_info: Option<Git>,
+    pub ignores: Ignores,
     pub bad_git_log: Logs,
     pub preview_cursor: usize,
-    pub preview_colours: bool,
+    /// somewhere in a big file to preview instead of its start, for as long as it's showing
+    pub preview_page: Option<(PathBuf, Page)>,
+    pub preview_style: PreviewStyle,
     pub ls_colors: LsColors,
     pub command_palette: CommandPalette,
+    pub disk_usage: Option<DiskUsage>,
+    pub sort: SortOrder,
+    pub capture_dates: CaptureDates,
+    pub cleanup: Option<Cleanup>,
+    /// picked outside of cleanup, by absolute path: archive members to extract
+    pub marked: HashSet<PathBuf>,
+    /// the input is a content search, rather than a filter on the listing
+    pub grep: bool,
+    pub preview_focus: PreviewFocus,
+    /// where the preview wants its image this frame, if the terminal is drawing it
+    pub placement: RefCell<Option<Placement>>,
+}
+
+/// Moving around and searching in the preview, rather than the listing
+#[derive(Default)]
+pub struct PreviewFocus {
+    pub focus: bool,
+    pub input: Input,
+    /// a line of the preview's render; a hit we jumped to, or wherever it's been moved
+    pub cursor: Option<usize>,
+    /// the other end of the selection, which runs to the cursor
+    pub anchor: Option<usize>,
+    /// compiled for the query it was last asked for
+    matcher: RefCell<Option<(String, RegexMatcher)>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum SortOrder {
+    /// directories first, then by name
+    #[default]
+    Name,
+    /// largest first, needs disk usage for directories
+    Size,
+    /// biggest and oldest first, for cleaning up
+    Stale,
+    /// photos by when they were taken, oldest first, then everything else by name
+    Taken,
+}
+
+impl SortOrder {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Name => Self::Size,
+            Self::Size => Self::Stale,
+            Self::Stale => Self::Taken,
+            Self::Taken => Self::Name,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Size => "size",
+            Self::Stale => "staleness",
+            Self::Taken => "date taken",
+        }
+    }
 }
 
 impl Ui {
     pub fn is_searching(&self) -> bool {
-        !self.input.value().is_empty()
+        !self.grep && !self.input.value().is_empty()
+    }
+
+    pub fn grep_query(&self) -> Option<String> {
+        self.grep.then(|| self.input.value().to_string())
     }
 
     pub fn cursor_showing_path(&self) -> Option<&Path> {
         self.cursor_showing.as_ref().and_then(|v| v.path())
     }
+
+    /// What to highlight in the preview: the preview search, or failing that,
+    /// the content search which found the item.
+    pub fn preview_query(&self) -> Option<&str> {
+        let search = self.preview_focus.input.value();
+        if !search.is_empty() {
+            return Some(search);
+        }
+        let content_match = self
+            .cursor_showing
+            .as_ref()
+            .is_some_and(|item| item.position().is_some());
+        Some(self.input.value()).filter(|q| self.grep && content_match && !q.is_empty())
+    }
+
+    pub fn preview_matcher(&self) -> Option<RegexMatcher> {
+        let query = self.preview_query()?;
+        let mut cached = self.preview_focus.matcher.borrow_mut();
+        if cached.as_ref().is_none_or(|(q, _)| q != query) {
+            *cached = Some((query.to_string(), matcher(query).ok()?));
+        }
+        cached.as_ref().map(|(_, m)| m.clone())
+    }
 }
 
 #[derive(Default)]
@@ -54,13 +163,292 @@ pub struct CommandPalette {
 }
 
 pub fn matching_preview(ui: &Ui, mode: PreviewMode) -> Option<&Preview> {
-    ui.previews.inner.iter().rev().find(|v| {
+    let line = cursor_line(ui);
+    let page = preview_page(ui);
+    let mut candidates = ui.previews.inner.iter().rev().filter(|v| {
         Some(v.showing.as_path()) == ui.cursor_showing_path()
             && v.mode == mode
-            && v.coloured == ui.preview_colours
+            && v.line == line
+            && v.page == page
+            && v.style == ui.preview_style
+    });
+    let newest = candidates.next()?;
+    if newest.is_finished() {
+        return Some(newest);
+    }
+    // keep showing an older render while a refresh loads, instead of flickering
+    Some(candidates.find(|v| v.is_finished()).unwrap_or(newest))
+}
+
+fn preview_page(ui: &Ui) -> Option<Page> {
+    let (path, page) = ui.preview_page.as_ref()?;
+    (Some(path.as_path()) == ui.cursor_showing_path()).then_some(*page)
+}
+
+/// What part of a big file the preview's showing, if it's being paged through.
+pub fn preview_window(ui: &Ui, mode: PreviewMode) -> Option<Window> {
+    let preview = matching_preview(ui, mode)?;
+    let data = preview.data.lock().expect("panic");
+    data.window
+}
+
+/// Show another page of the file, scrolled this far down it.
+pub fn turn_preview_page(ui: &mut Ui, page: Page, cursor: usize) {
+    let Some(path) = ui.cursor_showing_path().map(Path::to_path_buf) else {
+        return;
+    };
+    ui.preview_page = Some((path, page));
+    ui.preview_cursor = cursor;
+    ui.preview_focus.cursor = None;
+    ui.preview_focus.anchor = None;
+}
+
+/// Jump some way through the file: by turning to that page of a big one, or otherwise
+/// just by scrolling there.
+pub fn page_preview(ui: &mut Ui, mode: PreviewMode, percent: u8) {
+    if preview_window(ui, mode).is_some() {
+        turn_preview_page(ui, Page::Percent(percent), 0);
+        return;
+    }
+    let len = with_render(ui, mode, |render, _| render.lines.len()).unwrap_or_default();
+    ui.preview_cursor = len * usize::from(percent) / 100;
+}
+
+/// Go where the preview search says, if it's a line (`:120`) or a percentage (`50%`),
+/// rather than something to find; false if it's neither.
+pub fn jump_preview(ui: &mut Ui, mode: PreviewMode) -> bool {
+    let query = ui.preview_focus.input.value().trim();
+    let percent = query.strip_suffix('%').and_then(|p| p.parse::<u8>().ok());
+    if let Some(percent) = percent.filter(|&p| p <= 100) {
+        page_preview(ui, mode, percent);
+        return true;
+    }
+    let Some(line) = query
+        .strip_prefix(':')
+        .and_then(|l| l.parse::<usize>().ok())
+    else {
+        return false;
+    };
+    let found = with_render(ui, mode, |render, _| {
+        (1..render.lines.len()).find(|&i| source_line(render, i) == Some(line))
+    })
+    .flatten();
+    match (found, preview_window(ui, mode)) {
+        (Some(found), _) => {
+            ui.preview_focus.cursor = Some(found);
+            ui.preview_focus.anchor = None;
+            ui.preview_cursor = found.saturating_sub(PREVIEW_CONTEXT);
+        }
+        (
+            None,
+            Some(Window {
+                first: Page::Line(_),
+                ..
+            }),
+        ) => {
+            let page = Page::Line(line.saturating_sub(PREVIEW_CONTEXT).max(1));
+            turn_preview_page(ui, page, 0);
+        }
+        // past the end of a file that's all there
+        (None, _) => (),
+    }
+    true
+}
+
+/// Look at the preview's render, and what it was made from, if it's there.
+fn with_render<T>(
+    ui: &Ui,
+    mode: PreviewMode,
+    f: impl FnOnce(&Text<'static>, &PreviewedData) -> T,
+) -> Option<T> {
+    let preview = matching_preview(ui, mode)?;
+    let data = preview.data.lock().expect("panic");
+    Some(f(data.render.as_ref()?, &data))
+}
+
+fn plain(line: &Line) -> String {
+    line.spans.iter().map(|s| s.content.as_ref()).collect()
+}
+
+/// The lines of the preview's render which contain a match for the preview query.
+pub fn preview_hits(ui: &Ui, mode: PreviewMode) -> Vec<usize> {
+    let Some(matcher) = ui.preview_matcher() else {
+        return Vec::new();
+    };
+    with_render(ui, mode, |render, _| {
+        render
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| matcher.is_match(plain(line).as_bytes()).unwrap_or_default())
+            .map(|(i, _)| i)
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Scroll the preview to the next (or previous) hit, wrapping around at the ends.
+pub fn jump_to_hit(ui: &mut Ui, mode: PreviewMode, forward: bool) {
+    let hits = preview_hits(ui, mode);
+    let current = ui.preview_focus.cursor;
+    let hit = if forward {
+        let from = current.map_or(ui.preview_cursor, |c| c + 1);
+        hits.iter().find(|&&h| h >= from).or(hits.first())
+    } else {
+        let before = current.unwrap_or(ui.preview_cursor);
+        hits.iter().rev().find(|&&h| h < before).or(hits.last())
+    };
+    let Some(&hit) = hit else {
+        return;
+    };
+    ui.preview_focus.cursor = Some(hit);
+    ui.preview_focus.anchor = None;
+    // following is pinned to the end, so there's no scrolling to a line
+    if mode != PreviewMode::Follow {
+        ui.preview_cursor = hit.saturating_sub(PREVIEW_CONTEXT);
+    }
+}
+
+/// Move the preview's line cursor, scrolling to keep it in view.
+/// `extend` grows the selection, instead of dropping it.
+pub fn move_preview_line(
+    ui: &mut Ui,
+    mode: PreviewMode,
+    delta: isize,
+    extend: bool,
+    height: usize,
+) {
+    let len = with_render(ui, mode, |render, _| render.lines.len()).unwrap_or_default();
+    let Some(last) = len.checked_sub(1) else {
+        return;
+    };
+    let focus = &mut ui.preview_focus;
+    // start from the top of what's on screen
+    let from = focus.cursor.unwrap_or(ui.preview_cursor).min(last);
+    focus.anchor = if extend {
+        Some(focus.anchor.unwrap_or(from))
+    } else {
+        None
+    };
+    let to = from.saturating_add_signed(delta).min(last);
+    focus.cursor = Some(to);
+
+    if mode == PreviewMode::Follow {
+        return;
+    }
+    if to < ui.preview_cursor {
+        ui.preview_cursor = to;
+    } else if to >= ui.preview_cursor + height {
+        ui.preview_cursor = to + 1 - height;
+    }
+}
+
+/// The lines of the preview's render which are selected, top to bottom.
+pub fn preview_selection(ui: &Ui) -> Option<RangeInclusive<usize>> {
+    let focus = &ui.preview_focus;
+    let cursor = focus.cursor?;
+    let anchor = focus.anchor.unwrap_or(cursor);
+    Some(cursor.min(anchor)..=cursor.max(anchor))
+}
+
+/// The line of the file under the preview's cursor (or at the top of the selection),
+/// falling back to the line a content match is on.
+pub fn preview_source_line(ui: &Ui, mode: PreviewMode) -> Option<usize> {
+    // only bat's gutter has line numbers to go by
+    let from_cursor = preview_selection(ui)
+        .filter(|_| mode == PreviewMode::Content)
+        .and_then(|selected| {
+            with_render(ui, mode, |render, data| {
+                data.source
+                    .as_ref()
+                    .and_then(|_| source_line(render, *selected.start()))
+            })
+            .flatten()
+        });
+    from_cursor.or_else(|| cursor_line(ui))
+}
+
+/// The selected text: straight from the file where we can tell which lines are showing,
+/// otherwise as it's rendered.
+pub fn preview_selection_text(ui: &Ui, mode: PreviewMode) -> Option<String> {
+    let selected = preview_selection(ui)?;
+    // the content of a page of a big file starts partway through
+    let before = match preview_window(ui, mode) {
+        Some(Window {
+            first: Page::Line(first),
+            ..
+        }) => first - 1,
+        _ => 0,
+    };
+    with_render(ui, mode, |render, data| {
+        let lines = render.lines.get(selected.clone())?;
+        let source = data
+            .source
+            .as_ref()
+            .filter(|_| mode == PreviewMode::Content);
+        let first = source.and_then(|_| source_line(render, *selected.start()));
+        let last = source.and_then(|_| source_line(render, *selected.end()));
+        if let (Some(text), Some(first), Some(last)) = (source, first, last) {
+            let skip = first.checked_sub(before + 1)?;
+            let picked = text.lines().skip(skip).take(last + 1 - first);
+            return Some(picked.collect::<Vec<_>>().join("\n"));
+        }
+        Some(lines.iter().map(plain).collect::<Vec<_>>().join("\n"))
+    })
+    .flatten()
+}
+
+/// Which line of the file a line of the render shows, read back from the line numbers
+/// in its gutter; wrapped lines have an empty gutter, so belong to the line above.
+fn source_line(render: &Text, index: usize) -> Option<usize> {
+    // the header is always first
+    (1..=index).rev().find_map(|i| {
+        let line = plain(render.lines.get(i)?);
+        let number = line.trim_start_matches(' ');
+        let indent = line.len() - number.len();
+        let digits = number.bytes().take_while(u8::is_ascii_digit).count();
+        // a wrapped line's content starts after a whole empty gutter
+        if indent >= 4 || digits == 0 {
+            return None;
+        }
+        number[..digits].parse().ok()
     })
 }
 
+/// The line of the preview's render the cursor is on, or the top of what's on screen.
+fn preview_line(ui: &Ui) -> usize {
+    ui.preview_focus.cursor.unwrap_or(ui.preview_cursor)
+}
+
+/// The key path to what's on the tree preview's line, like `.foo[3].bar`.
+pub fn preview_key_path(ui: &Ui, mode: PreviewMode) -> Option<String> {
+    let preview = matching_preview(ui, mode)?;
+    let data = preview.data.lock().expect("panic");
+    data.tree.as_ref()?.path(preview_line(ui))
+}
+
+/// Fold, or unfold, what's on the tree preview's line; false if there's nothing to fold.
+pub fn toggle_preview_fold(ui: &Ui, mode: PreviewMode) -> bool {
+    let Some(preview) = matching_preview(ui, mode) else {
+        return false;
+    };
+    let mut data = preview.data.lock().expect("panic");
+    let Some(tree) = data.tree.as_mut() else {
+        return false;
+    };
+    if !tree.toggle(preview_line(ui)) {
+        return false;
+    }
+    let render = tree.render();
+    data.render = Some(render);
+    true
+}
+
+fn cursor_line(ui: &Ui) -> Option<usize> {
+    let (line, _) = ui.cursor_showing.as_ref()?.position()?;
+    usize::try_from(line).ok()
+}
+
 pub fn trigger_right_pane(ui: &mut Ui, view_opts: ViewOpts, pane_area: Rect) {
     if pane_area.width == 0 || pane_area.height == 0 {
         return;
@@ -101,44 +489,115 @@ pub fn fire_preview(ui: &mut Ui, mode: PreviewMode, preview_area: Rect) {
 
     let started = Instant::now();
 
-    if ui.previews.inner.iter().rev().any(|v| {
-        Some(v.showing.as_path()) == ui.cursor_showing_path()
-            && v.target_area == area
+    // following keeps itself up to date
+    let stamp = match mode {
+        PreviewMode::Follow => None,
+        _ => stamp(&showing),
+    };
+
+    let line = cursor_line(ui);
+    let page = preview_page(ui);
+    // less the header
+    let rows = usize::from(preview_area.height.saturating_sub(1));
+
+    // only what's on screen is worth finishing; dropping the rest cancels them
+    ui.previews
+        .inner
+        .retain(|v| v.is_finished() || (v.showing == showing && v.mode == mode));
+
+    // a taller render is fine, there's just some we don't show
+    let reusable = ui.previews.inner.iter().rposition(|v| {
+        v.showing == showing
+            && v.target_area.width == area.width
+            && v.target_area.height >= area.height
             && v.mode == mode
-            && v.coloured == ui.preview_colours
-    }) {
+            && v.line == line
+            && v.page == page
+            && v.style == ui.preview_style
+            && v.stamp == stamp
+    });
+    if let Some(i) = reusable {
+        // least recently used go first
+        if let Some(hit) = ui.previews.inner.remove(i) {
+            ui.previews.inner.push_back(hit);
+        }
         return;
     }
 
-    if ui.previews.inner.len() >= 16 {
-        ui.previews.inner.pop_front();
-    }
-
     let data = Arc::new(Mutex::new(PreviewedData::default()));
+    let preview = Preview {
+        showing: showing.to_path_buf(),
+        mode,
+        target_area: area,
+        style: ui.preview_style,
+        stamp,
+        line,
+        page,
+        data: Arc::clone(&data),
+        finished: Arc::default(),
+        cancelled: Arc::default(),
+        started,
+    };
 
-    let write_to = Arc::clone(&data);
+    let finished = Arc::clone(&preview.finished);
+    let cancelled = Arc::clone(&preview.cancelled);
     let preview_path = showing.to_path_buf();
-    let coloured = ui.preview_colours;
-    let worker = thread::spawn(move || {
-        if let Err(e) = run_preview(&preview_path, coloured, mode, Arc::clone(&write_to), area) {
-            write_to
-                .lock()
+    let style = ui.preview_style;
+    let previewers = Arc::clone(&ui.previews.previewers);
+    let line_indexes = Arc::clone(&ui.previews.line_indexes);
+    // any mode which can't show a directory falls back to listing it
+    let listing = if showing.is_dir() {
+        Listing {
+            ls_colors: ui.ls_colors.clone(),
+            changes: ui
+                .git_info
+                .as_ref()
+                .map(|git| git.changes_under(&showing))
+                .unwrap_or_default(),
+        }
+    } else {
+        Listing::default()
+    };
+    ui.previews.run(&preview, move || {
+        // bat, the image decoders and hexyl have all been known to panic; that should
+        // only cost this preview
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let request = PreviewRequest {
+                style,
+                area,
+                line,
+                paging: Paging {
+                    page,
+                    rows,
+                    indexes: &line_indexes,
+                },
+                previewers: &previewers,
+                listing: &listing,
+                cancelled: &cancelled,
+            };
+            run_preview(&preview_path, mode, Arc::clone(&data), &request)
+        }))
+        .unwrap_or_else(|panic| Err(anyhow!("panicked: {}", panic_message(&*panic))));
+        if let Err(e) = result {
+            data.clear_poison();
+            data.lock()
                 .expect("panic")
                 .content
                 .extend_from_slice(format!("Error: {}\n", e).as_bytes());
         }
-        info!("preview: {preview_path:?} took {:?}", started.elapsed());
+        finished.store(true, Ordering::Relaxed);
+        if cancelled.load(Ordering::Relaxed) {
+            info!(
+                "preview: {preview_path:?} cancelled after {:?}",
+                started.elapsed()
+            );
+        } else {
+            info!("preview: {preview_path:?} took {:?}", started.elapsed());
+        }
     });
 
-    ui.previews.inner.push_back(Preview {
-        showing: showing.to_path_buf(),
-        mode,
-        target_area: area,
-        coloured: ui.preview_colours,
-        data,
-        worker,
-        started,
-    });
+    ui.previews.inner.push_back(preview);
+    ui.previews.evict();
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -1,8 +1,14 @@
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::archive::{split, stream_archive};
 use crate::fuzz::AddItem;
+use crate::grep::stream_grep;
 use crate::item::{convert, Item};
+use crate::path_index::PathIndex;
+use crate::text_index::TextIndex;
+use crate::tracked::stream_tracked;
 
 use anyhow::Result;
 use ignore::{DirEntry, Error as DirEntryError, WalkBuilder, WalkState};
@@ -15,8 +21,18 @@ pub struct ReadOpts {
     pub show_ignored: bool,
     pub mode_index: usize,
     pub recursion: Recursion,
+    pub source: Source,
     pub target_dir: PathBuf,
     pub expansions: HashSet<PathBuf>,
+    pub watch: bool,
+    /// searching file contents for this, instead of listing files
+    pub grep: Option<String>,
+    /// narrows down which files `grep` needs to read
+    pub index: Option<Arc<TextIndex>>,
+    /// answers recursive listings without walking
+    pub paths: Option<Arc<PathIndex>>,
+    /// listing everything in `paths`, wherever we are
+    pub global: bool,
 }
 
 #[derive(Copy, Clone, clap::ValueEnum, PartialEq, Eq, Debug)]
@@ -28,15 +44,50 @@ pub enum Mode {
 
 pub const MODES: [Mode; 3] = [Mode::Mixed, Mode::Files, Mode::Dirs];
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Mixed => "mixed",
+            Mode::Files => "files",
+            Mode::Dirs => "dirs",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
 pub enum Recursion {
+    #[default]
     None = 0,
     All = 1,
 }
 
-impl Default for Recursion {
-    fn default() -> Self {
-        Self::None
+/// Where the listing comes from
+#[derive(Copy, Clone, clap::ValueEnum, PartialEq, Eq, Debug, Default)]
+pub enum Source {
+    /// walk the filesystem
+    #[default]
+    Walk,
+    /// paths from the git index, like `git ls-files`
+    Tracked,
+    /// paths from the git index, then untracked but not ignored files
+    TrackedUntracked,
+}
+
+impl Source {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Walk => Self::Tracked,
+            Self::Tracked => Self::TrackedUntracked,
+            Self::TrackedUntracked => Self::Walk,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Walk => "walk",
+            Self::Tracked => "tracked",
+            Self::TrackedUntracked => "tracked+untracked",
+        }
     }
 }
 
@@ -51,6 +102,36 @@ impl Recursion {
 
 pub fn stream_content(tx: AddItem, src: impl AsRef<Path>, read_opts: &ReadOpts) -> Result<()> {
     let src = src.as_ref();
+    if let Some((archive, prefix)) = split(src) {
+        return stream_archive(tx, archive, prefix, read_opts);
+    }
+
+    if let Some(query) = &read_opts.grep {
+        return stream_grep(tx, src, query, read_opts);
+    }
+
+    if read_opts.global {
+        if let Some(paths) = &read_opts.paths {
+            paths.stream_global(&tx, read_opts);
+        }
+        return Ok(());
+    }
+
+    match read_opts.source {
+        Source::Walk => (),
+        Source::Tracked => return stream_tracked(tx, src, read_opts, false),
+        Source::TrackedUntracked => return stream_tracked(tx, src, read_opts, true),
+    }
+
+    // the index only knows about what a default walk would find
+    if read_opts.recursion == Recursion::All && !read_opts.show_hidden && !read_opts.show_ignored {
+        if let Some(paths) = &read_opts.paths {
+            if paths.stream(&tx, src, read_opts) {
+                return Ok(());
+            }
+        }
+    }
+
     if read_opts.recursion == Recursion::None {
         for exp in &read_opts.expansions {
             stream_rel_content(tx.clone(), src, exp, read_opts);
@@ -61,6 +142,27 @@ pub fn stream_content(tx: AddItem, src: impl AsRef<Path>, read_opts: &ReadOpts)
     Ok(())
 }
 
+pub fn mode_accepts(read_opts: &ReadOpts, item: &Item) -> bool {
+    match (MODES[read_opts.mode_index], item.info()) {
+        (Mode::Files, Some(info)) => info.kind.is_file(),
+        (Mode::Dirs, Some(info)) => info.kind.is_dir(),
+        _ => true,
+    }
+}
+
+/// What a walk of `src` finds, as the listing shows it: all the way down, for callers to limit.
+pub fn walk_builder(src: impl AsRef<Path>, read_opts: &ReadOpts) -> WalkBuilder {
+    let ignore_files = !read_opts.show_ignored;
+    let mut walk = WalkBuilder::new(src);
+    walk.follow_links(true)
+        .hidden(!read_opts.show_hidden)
+        .ignore(ignore_files)
+        .git_exclude(ignore_files)
+        .git_global(ignore_files)
+        .git_ignore(ignore_files);
+    walk
+}
+
 pub fn stream_rel_content(
     tx: AddItem,
     root: impl AsRef<Path>,
@@ -71,20 +173,8 @@ pub fn stream_rel_content(
 
     /* @return true if we should early exit */
     let maybe_send = |tx: &AddItem, f: Item| {
-        if let Item::FileEntry { info, .. } = &f {
-            match MODES[read_opts.mode_index] {
-                Mode::Mixed => (),
-                Mode::Files => {
-                    if !info.file_type.is_file() {
-                        return false;
-                    }
-                }
-                Mode::Dirs => {
-                    if !info.file_type.is_dir() {
-                        return false;
-                    }
-                }
-            }
+        if !mode_accepts(read_opts, &f) {
+            return false;
         }
 
         tx.send(f).is_err()
@@ -95,31 +185,22 @@ pub fn stream_rel_content(
         Recursion::All => None,
     };
 
-    let ignore_files = !read_opts.show_ignored;
-    let src = src.as_ref().to_path_buf();
-    let mut walk = WalkBuilder::new(src);
-    let walk = walk
-        .follow_links(true)
-        .hidden(!read_opts.show_hidden)
-        .ignore(ignore_files)
-        .git_exclude(ignore_files)
-        .git_global(ignore_files)
-        .git_ignore(ignore_files)
-        .max_depth(max_depth);
-
-    walk.build_parallel().run(|| {
-        let tx = tx.clone();
-        let root = root.clone();
-        Box::new(move |f: DResult| {
-            if let Some(item) = convert(&root, f) {
-                if maybe_send(&tx, item) {
-                    WalkState::Quit
+    walk_builder(src, read_opts)
+        .max_depth(max_depth)
+        .build_parallel()
+        .run(|| {
+            let tx = tx.clone();
+            let root = root.clone();
+            Box::new(move |f: DResult| {
+                if let Some(item) = convert(&root, f) {
+                    if maybe_send(&tx, item) {
+                        WalkState::Quit
+                    } else {
+                        WalkState::Continue
+                    }
                 } else {
                     WalkState::Continue
                 }
-            } else {
-                WalkState::Continue
-            }
-        })
-    });
+            })
+        });
 }
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use crate::ignores::Ignores;
+use crate::item::{convert_path, Item};
+use crate::walk::{mode_accepts, ReadOpts, Recursion};
+use anyhow::Result;
+use log::info;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Above this many items, recursive listings only watch the top directory;
+/// inotify needs a watch per directory, and the default limits are small.
+const RECURSIVE_WATCH_LIMIT: u32 = 20_000;
+
+pub struct Watch {
+    // kept alive for the watches
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+    /// new items, by absolute path, until the ignore worker says they aren't ignored
+    waiting: Vec<(PathBuf, Item)>,
+    ignores: Ignores,
+}
+
+#[derive(Default)]
+pub struct Changes {
+    /// created, or changed enough that the item needs rebuilding
+    pub upserted: HashSet<PathBuf>,
+    pub removed: HashSet<PathBuf>,
+    /// only the content changed; the item is fine, but anything derived from it isn't
+    pub touched: HashSet<PathBuf>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.upserted.is_empty() && self.removed.is_empty() && self.touched.is_empty()
+    }
+
+    /// Events are taken in order, and only the last says what happened to a path:
+    /// saving by renaming the old file away and writing a new one leaves it there.
+    fn upsert(&mut self, path: PathBuf) {
+        self.removed.remove(&path);
+        self.upserted.insert(path);
+    }
+
+    fn remove(&mut self, path: PathBuf) {
+        self.upserted.remove(&path);
+        self.removed.insert(path);
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &PathBuf> {
+        self.upserted
+            .iter()
+            .chain(self.removed.iter())
+            .chain(self.touched.iter())
+    }
+}
+
+impl Watch {
+    pub fn new(here: &Path, read_opts: &ReadOpts, item_count: u32) -> Result<Self> {
+        let (tx, rx) = channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+
+        match read_opts.recursion {
+            Recursion::None => {
+                watcher.watch(here, RecursiveMode::NonRecursive)?;
+                for exp in &read_opts.expansions {
+                    watcher.watch(exp, RecursiveMode::NonRecursive)?;
+                }
+            }
+            Recursion::All if item_count > RECURSIVE_WATCH_LIMIT => {
+                info!("watch: {item_count} items, only watching {here:?}");
+                watcher.watch(here, RecursiveMode::NonRecursive)?;
+            }
+            Recursion::All => {
+                if let Err(e) = watcher.watch(here, RecursiveMode::Recursive) {
+                    info!("watch: recursive watch failed ({e}), only watching {here:?}");
+                    let _ = watcher.unwatch(here);
+                    watcher.watch(here, RecursiveMode::NonRecursive)?;
+                }
+            }
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+            waiting: Vec::new(),
+            ignores: Ignores::default(),
+        })
+    }
+
+    /// The items for what's been upserted which the listing would show, as far as
+    /// that's known; ones waiting on the ignore rules come back from a later call.
+    pub fn listed_items(
+        &mut self,
+        here: &Path,
+        read_opts: &ReadOpts,
+        changes: &Changes,
+    ) -> Vec<Item> {
+        self.waiting.retain(|(path, _)| {
+            !changes.upserted.contains(path) && !changes.removed.iter().any(|r| path.starts_with(r))
+        });
+        self.waiting.extend(
+            changes
+                .upserted
+                .iter()
+                .filter_map(|path| Some((path.clone(), listed_item(here, read_opts, path)?))),
+        );
+        if read_opts.show_ignored {
+            return self.waiting.drain(..).map(|(_, item)| item).collect();
+        }
+
+        let mut ready = Vec::new();
+        self.waiting.retain(|(path, item)| {
+            let is_dir = item.info().map(|i| i.kind.is_dir()).unwrap_or_default();
+            match self.ignores.decided(path, is_dir) {
+                None => true,
+                Some(None) => {
+                    ready.push(item.clone());
+                    false
+                }
+                Some(Some(_)) => false,
+            }
+        });
+        ready
+    }
+
+    pub fn drain(&self) -> Changes {
+        let mut changes = Changes::default();
+        for event in self.rx.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    info!("watch: {e}");
+                    continue;
+                }
+            };
+            let mut paths = event.paths.into_iter();
+            match event.kind {
+                EventKind::Create(_) => paths.for_each(|path| changes.upsert(path)),
+                EventKind::Remove(_) => paths.for_each(|path| changes.remove(path)),
+                EventKind::Modify(ModifyKind::Data(_)) => changes.touched.extend(paths),
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                    if let Some(from) = paths.next() {
+                        changes.remove(from);
+                    }
+                    paths.for_each(|path| changes.upsert(path));
+                }
+                EventKind::Modify(_) => {
+                    for path in paths {
+                        if path.symlink_metadata().is_ok() {
+                            changes.upsert(path);
+                        } else {
+                            changes.remove(path);
+                        }
+                    }
+                }
+                EventKind::Access(_) | EventKind::Any | EventKind::Other => (),
+            }
+        }
+
+        changes
+    }
+}
+
+/// An item for `path`, if the current listing would have shown it, ignore files aside.
+fn listed_item(here: &Path, read_opts: &ReadOpts, path: &Path) -> Option<Item> {
+    let parent = path.parent()?;
+    let in_listing = match read_opts.recursion {
+        Recursion::None => parent == here || read_opts.expansions.contains(parent),
+        Recursion::All => path.starts_with(here),
+    };
+    if !in_listing {
+        return None;
+    }
+
+    let rel = path.strip_prefix(here).ok()?;
+    let hidden = rel
+        .components()
+        .any(|c| c.as_os_str().as_encoded_bytes().starts_with(b"."));
+    if hidden && !read_opts.show_hidden {
+        return None;
+    }
+
+    let item = convert_path(here, path.to_path_buf()).ok()??;
+    mode_accepts(read_opts, &item).then_some(item)
+}